@@ -4,10 +4,28 @@ use crate::prelude::*;
 pub struct AccountBalance {
   pub address: String,
   pub balance: String,
+  // Balance in the smallest token unit
+  pub balance_raw: String,
+  // Percentage of the token total supply held by the address, only present when requested
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub share_of_supply: Option<String>,
+  // Reverse-resolved ENS primary name of the address, only present when requested and set
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
 }
 
 impl AccountBalance {
-  pub fn new(address: String, balance: String) -> Self {
-    AccountBalance { address, balance }
+  pub fn new(address: String, balance: String, balance_raw: String) -> Self {
+    AccountBalance { address, balance, balance_raw, share_of_supply: None, name: None }
+  }
+
+  pub fn with_share_of_supply(mut self, share_of_supply: Option<String>) -> Self {
+    self.share_of_supply = share_of_supply;
+    self
+  }
+
+  pub fn with_name(mut self, name: Option<String>) -> Self {
+    self.name = name;
+    self
   }
 }
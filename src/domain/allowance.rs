@@ -0,0 +1,17 @@
+use crate::prelude::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Allowance {
+  pub owner: String,
+  pub spender: String,
+  // Allowance formatted with the token decimals
+  pub allowance: String,
+  // Allowance in the smallest token unit
+  pub allowance_raw: String,
+}
+
+impl Allowance {
+  pub fn new(owner: String, spender: String, allowance: String, allowance_raw: String) -> Self {
+    Allowance { owner, spender, allowance, allowance_raw }
+  }
+}
@@ -0,0 +1,87 @@
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressKind {
+  // Externally owned account, no code deployed at the address
+  Eoa,
+  Contract,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStandard {
+  Erc20,
+  Erc165,
+  Erc721,
+  Erc1155,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+  // Implementation address kept in the EIP-1967 implementation slot
+  Eip1967,
+  // Implementation provided by the beacon kept in the EIP-1967 beacon slot
+  Eip1967Beacon,
+  // EIP-1167 minimal proxy, the implementation address is part of the code
+  Eip1167,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProxyInfo {
+  pub kind: ProxyKind,
+  pub implementation: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractKind {
+  pub address: String,
+  pub kind: AddressKind,
+  // Standards the contract implements, through its implementation for proxies
+  pub standards: Vec<TokenStandard>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub proxy: Option<ProxyInfo>,
+}
+
+impl ContractKind {
+  pub fn new(address: String, kind: AddressKind, standards: Vec<TokenStandard>, proxy: Option<ProxyInfo>) -> Self {
+    ContractKind { address, kind, standards, proxy }
+  }
+}
+
+// ABI given with a contract call, either the name of a stored ABI or an inline JSON ABI or fragment
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AbiSource {
+  Stored(String),
+  Inline(serde_json::Value),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContractCallRequest {
+  pub abi: AbiSource,
+  // Function name, or full signature such as `balanceOf(address)` to pick an overload
+  pub function: String,
+  // Arguments in input order, integers as JSON numbers or decimal strings
+  #[serde(default)]
+  pub args: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractCallOutput {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(rename = "type")]
+  pub kind: String,
+  // Integers are decimal strings, bytes 0x-prefixed hex, tuples arrays of their components
+  pub value: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractCallResult {
+  pub contract: String,
+  // Canonical signature of the called function
+  pub function: String,
+  pub outputs: Vec<ContractCallOutput>,
+}
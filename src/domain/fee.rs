@@ -0,0 +1,37 @@
+use crate::prelude::*;
+
+// EIP-1559 fees paying the priority fee seen at a percentile of recent blocks. Fees are in wei, costs in ether.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeeSuggestion {
+  pub percentile: f64,
+  pub max_priority_fee_per_gas: String,
+  // Twice the next base fee plus the priority fee, so the transaction stays includable if the base fee rises
+  pub max_fee_per_gas: String,
+  // Gas limit times next base fee plus priority fee, absent when the gas could not be estimated
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub estimated_cost: Option<String>,
+  // Gas limit times max fee, the most the sender can be charged
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_cost: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferEstimate {
+  pub token: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub symbol: Option<String>,
+  pub from: String,
+  pub to: String,
+  // Amount formatted with the token decimals
+  pub amount: String,
+  pub amount_raw: String,
+  pub will_revert: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub revert_reason: Option<String>,
+  // Absent when the transfer would revert
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gas_limit: Option<String>,
+  // Base fee of the next block, in wei
+  pub base_fee_per_gas: String,
+  pub fees: Vec<FeeSuggestion>,
+}
@@ -1,2 +1,10 @@
 pub mod token;
 pub mod account;
+pub mod allowance;
+pub mod transfer;
+pub mod user;
+pub mod nft;
+pub mod multitoken;
+pub mod contract;
+pub mod transaction;
+pub mod fee;
@@ -0,0 +1,30 @@
+use crate::prelude::*;
+
+// Balance of one token id of an ERC-1155 contract held by one account
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultiTokenBalance {
+  pub address: String,
+  pub id: String,
+  // Amount in the smallest unit, ERC-1155 has no decimals on chain
+  pub balance: String,
+}
+
+impl MultiTokenBalance {
+  pub fn new(address: String, id: String, balance: String) -> Self {
+    MultiTokenBalance { address, id, balance }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultiTokenUri {
+  pub id: String,
+  // Metadata URI with the `{id}` placeholder substituted, absent when the contract does not expose one
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub uri: Option<String>,
+}
+
+impl MultiTokenUri {
+  pub fn new(id: String, uri: Option<String>) -> Self {
+    MultiTokenUri { id, uri }
+  }
+}
@@ -0,0 +1,60 @@
+use crate::prelude::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NftCollection {
+  // Name and symbol come from the optional ERC-721 metadata extension and are left out when it is not implemented
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub symbol: Option<String>,
+  // Number of tokens in existence, only known for collections implementing the enumerable extension
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub total_supply: Option<String>,
+}
+
+impl NftCollection {
+  pub fn new(name: Option<String>, symbol: Option<String>, total_supply: Option<String>) -> Self {
+    NftCollection { name, symbol, total_supply }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NftOwner {
+  pub token_id: String,
+  // Absent when the token does not exist, e.g. it was never minted or has been burned
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub owner: Option<String>,
+}
+
+impl NftOwner {
+  pub fn new(token_id: String, owner: Option<String>) -> Self {
+    NftOwner { token_id, owner }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NftBalance {
+  pub address: String,
+  // Number of tokens of the collection owned by the address
+  pub balance: String,
+}
+
+impl NftBalance {
+  pub fn new(address: String, balance: String) -> Self {
+    NftBalance { address, balance }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NftTokenUri {
+  pub token_id: String,
+  // Absent when the token does not exist or the collection has no metadata extension
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token_uri: Option<String>,
+}
+
+impl NftTokenUri {
+  pub fn new(token_id: String, token_uri: Option<String>) -> Self {
+    NftTokenUri { token_id, token_uri }
+  }
+}
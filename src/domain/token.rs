@@ -2,13 +2,21 @@ use crate::prelude::*;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenInfo {
-  pub name: String,
-  pub symbol: String,
-  pub decimals: i32,
+  // Metadata the token does not expose is left out, amounts of a token without decimals are in raw units
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub symbol: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub decimals: Option<i32>,
+  // Total supply formatted with the token decimals
+  pub total_supply: String,
+  // Total supply in the smallest token unit
+  pub total_supply_raw: String,
 }
 
 impl TokenInfo {
-  pub fn new(name: String, symbol: String, decimals: i32) -> Self {
-    TokenInfo { name, symbol, decimals }
+  pub fn new(name: Option<String>, symbol: Option<String>, decimals: Option<i32>, total_supply: String, total_supply_raw: String) -> Self {
+    TokenInfo { name, symbol, decimals, total_supply, total_supply_raw }
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,174 @@
+use crate::prelude::*;
+
+// ERC20 call recognised in the calldata of a transaction sent to a token contract
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Erc20Intent {
+  Transfer {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    recipient: String,
+    // Amount formatted with the token decimals
+    amount: String,
+    amount_raw: String,
+  },
+  Approve {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    spender: String,
+    amount: String,
+    amount_raw: String,
+  },
+}
+
+// A signed transaction as decoded before broadcasting, with the hash it was accepted under
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastTransaction {
+  pub hash: String,
+  // legacy, eip2930 or eip1559
+  pub transaction_type: String,
+  pub chain_id: u64,
+  // Sender recovered from the signature
+  pub from: String,
+  // Absent for contract creations
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub to: Option<String>,
+  pub nonce: String,
+  pub gas_limit: String,
+  // Value in ether
+  pub value: String,
+  // Value in wei
+  pub value_raw: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub erc20: Option<Erc20Intent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+  // Known to the node but not mined yet
+  Pending,
+  Success,
+  // Mined but reverted
+  Failed,
+}
+
+// ERC20 event decoded from a receipt log, amounts formatted with the emitting token's decimals
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Erc20Log {
+  Transfer {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    log_index: u64,
+    from: String,
+    to: String,
+    amount: String,
+    amount_raw: String,
+  },
+  Approval {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    log_index: u64,
+    owner: String,
+    spender: String,
+    amount: String,
+    amount_raw: String,
+  },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionDetails {
+  pub hash: String,
+  pub status: TransactionStatus,
+  pub from: String,
+  // Absent for contract creations
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub to: Option<String>,
+  // Address of the contract created by the transaction
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub contract_address: Option<String>,
+  // Receipt fields, absent while the transaction is pending
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub block_number: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub block_hash: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gas_used: Option<String>,
+  // Price paid per unit of gas, in wei
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub effective_gas_price: Option<String>,
+  // Number of blocks mined on top of the transaction's block, plus one
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub confirmations: Option<u64>,
+  pub erc20_logs: Vec<Erc20Log>,
+}
+
+// What a transaction built for an external signer should do. Amounts are human-readable.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionIntent {
+  // Amount in ether
+  NativeTransfer { to: String, amount: String },
+  // Amount converted with the token decimals
+  Erc20Transfer { token: String, to: String, amount: String },
+  Erc20Approve { token: String, spender: String, amount: String },
+  ContractCall {
+    to: String,
+    // 0x-prefixed calldata
+    data: String,
+    // Value sent along, in ether
+    #[serde(default)]
+    value: Option<String>,
+  },
+}
+
+// Priority fee percentile the built transaction pays, slow to fast
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSpeed {
+  Slow,
+  #[default]
+  Standard,
+  Fast,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionBuildRequest {
+  pub from: String,
+  pub intent: TransactionIntent,
+  #[serde(default)]
+  pub speed: FeeSpeed,
+  // Attach the access list returned by eth_createAccessList
+  #[serde(default)]
+  pub access_list: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessListEntry {
+  pub address: String,
+  pub storage_keys: Vec<String>,
+}
+
+// Fully populated unsigned EIP-1559 transaction. Quantities are decimal strings in wei.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+  pub chain_id: u64,
+  pub from: String,
+  pub to: String,
+  pub nonce: String,
+  pub gas_limit: String,
+  pub max_fee_per_gas: String,
+  pub max_priority_fee_per_gas: String,
+  pub value: String,
+  pub data: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub access_list: Option<Vec<AccessListEntry>>,
+  // 0x02 || rlp(fields), the EIP-2718 payload whose keccak256 is signed
+  pub signing_payload: String,
+  pub signing_hash: String,
+}
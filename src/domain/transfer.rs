@@ -0,0 +1,103 @@
+use crate::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenTransfer {
+  pub block_number: u64,
+  pub transaction_hash: String,
+  pub log_index: u64,
+  pub from: String,
+  pub to: String,
+  // Amount formatted with the token decimals
+  pub amount: String,
+  // Amount in the smallest token unit
+  pub amount_raw: String,
+}
+
+impl TokenTransfer {
+  pub fn cursor(&self) -> TransferCursor {
+    TransferCursor::new(self.block_number, self.log_index)
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferPage {
+  pub transfers: Vec<TokenTransfer>,
+  // Cursor to pass back for the next page, absent once the requested block range is exhausted.
+  // A page cut short by the scan limit carries a cursor even when it holds fewer transfers than requested.
+  pub next_cursor: Option<String>,
+}
+
+impl TransferPage {
+  pub fn new(transfers: Vec<TokenTransfer>, next_cursor: Option<TransferCursor>) -> Self {
+    TransferPage { transfers, next_cursor: next_cursor.map(|cursor| cursor.to_string()) }
+  }
+}
+
+// Filter for a transfer history page, block bounds are inclusive
+#[derive(Debug)]
+pub struct TransferQuery {
+  // Only keep transfers where this address is the sender or the recipient
+  pub address: Option<String>,
+  pub from_block: Option<u64>,
+  pub to_block: Option<u64>,
+  // Only keep transfers strictly after this position
+  pub cursor: Option<TransferCursor>,
+  pub limit: usize,
+}
+
+// Position of a log on chain, rendered as `block:logIndex`.
+// Logs are totally ordered by it, so a cursor stays valid no matter how many blocks are added after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransferCursor {
+  pub block_number: u64,
+  pub log_index: u64,
+}
+
+impl TransferCursor {
+  pub fn new(block_number: u64, log_index: u64) -> Self {
+    TransferCursor { block_number, log_index }
+  }
+}
+
+impl fmt::Display for TransferCursor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.block_number, self.log_index)
+  }
+}
+
+impl FromStr for TransferCursor {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (block_number, log_index) = s
+      .split_once(':')
+      .ok_or_else(|| format!("expected block:logIndex, got [{}]", s))?;
+    let block_number = block_number.trim().parse::<u64>().map_err(|e| format!("block [{}] {}", block_number, e))?;
+    let log_index = log_index.trim().parse::<u64>().map_err(|e| format!("log index [{}] {}", log_index, e))?;
+
+    Ok(TransferCursor::new(block_number, log_index))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_transfer_cursor_round_trip() {
+    let cursor: TransferCursor = "1024:7".parse().unwrap();
+
+    assert_eq!(cursor, TransferCursor::new(1024, 7));
+    assert_eq!(cursor.to_string(), "1024:7");
+    assert!(TransferCursor::new(1024, 7) < TransferCursor::new(1024, 8));
+    assert!(TransferCursor::new(1024, 9) < TransferCursor::new(1025, 0));
+    // Cursor of a page cut short by the scan limit, past every log of its block
+    let end_of_block = TransferCursor::new(1024, u64::MAX);
+    assert_eq!(end_of_block.to_string().parse::<TransferCursor>().unwrap(), end_of_block);
+    assert!(TransferCursor::new(1024, 500) < end_of_block && end_of_block < TransferCursor::new(1025, 0));
+    assert!("1024".parse::<TransferCursor>().is_err());
+    assert!("latest:0".parse::<TransferCursor>().is_err());
+  }
+}
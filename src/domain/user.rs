@@ -0,0 +1,20 @@
+use crate::prelude::*;
+use crate::domain::account::AccountBalance;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserPortfolio {
+  pub user: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub symbol: Option<String>,
+  pub balances: Vec<AccountBalance>,
+  // Sum of all address balances formatted with the token decimals
+  pub total: String,
+  // Sum of all address balances in the smallest token unit
+  pub total_raw: String,
+}
+
+impl UserPortfolio {
+  pub fn new(user: String, symbol: Option<String>, balances: Vec<AccountBalance>, total: String, total_raw: String) -> Self {
+    UserPortfolio { user, symbol, balances, total, total_raw }
+  }
+}
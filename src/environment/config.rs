@@ -1,14 +1,300 @@
 use crate::error::AppServerError;
 use crate::prelude::*;
+use reqwest::Url;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Where a provider is reached: an http(s):// or ws(s):// url, or the path of a node's IPC socket
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderEndpoint {
+    Http(Url),
+    Ws(Url),
+    Ipc(PathBuf),
+}
+
+impl FromStr for ProviderEndpoint {
+    type Err = String;
+
+    fn from_str(provider_url: &str) -> Result<Self, Self::Err> {
+        let provider_url = provider_url.trim();
+        let scheme = provider_url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        match scheme.as_deref() {
+            Some("http") | Some("https") => provider_url.parse().map(ProviderEndpoint::Http).map_err(|e| e.to_string()),
+            Some("ws") | Some("wss") => provider_url.parse().map(ProviderEndpoint::Ws).map_err(|e| e.to_string()),
+            Some("ipc") => Ok(ProviderEndpoint::Ipc(PathBuf::from(&provider_url["ipc://".len()..]))),
+            Some(scheme) => Err(format!("unsupported scheme {}", scheme)),
+            None if provider_url.starts_with('/') || provider_url.ends_with(".ipc") => Ok(ProviderEndpoint::Ipc(PathBuf::from(provider_url))),
+            None => Err("expected an http(s)://, ws(s):// url or an IPC socket path".to_string()),
+        }
+    }
+}
+
+// Metadata configured for a token whose contract reports it wrongly or not at all
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenOverride {
+    pub address: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub decimals: Option<u8>,
+}
+
+// How requests are spread over the provider urls of a chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderStrategy {
+    // Always use the first healthy provider, falling back to the next one on failure
+    #[default]
+    PrimaryFallback,
+    // Rotate the starting provider on every request, falling back on failure
+    RoundRobin,
+    // Send every request to all healthy providers and require `quorum` identical answers
+    Quorum,
+}
+
+// One EVM chain served by the API, addressed in routes by name or chain id
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainConfig {
+    pub name: String,
+    // EIP-155 chain id, when unset the chain is only addressable by name
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    pub provider_urls: Vec<String>,
+    #[serde(default)]
+    pub provider_strategy: ProviderStrategy,
+    // Number of providers that must agree with the quorum strategy, defaults to a majority
+    #[serde(default)]
+    pub quorum: Option<usize>,
+    // Overrides the top-level multicall_address for this chain
+    #[serde(default)]
+    pub multicall_address: Option<String>,
+    // Overrides the top-level ens_registry_address for this chain
+    #[serde(default)]
+    pub ens_registry_address: Option<String>,
+    // Token metadata that replaces what the token contracts on this chain report
+    #[serde(default)]
+    pub token_overrides: Vec<TokenOverride>,
+}
+
+// Name of the single chain served from `provider_url` when no chains table is configured
+const LEGACY_CHAIN_NAME: &str = "ethereum";
 
 #[derive(Debug, Deserialize)]
 pub struct EnvConfig {
+    // Provider of the default chain for configs without a chains table
+    #[serde(default)]
     pub provider_url: String,
+    #[serde(default)]
+    pub chains: Vec<ChainConfig>,
+    // Chain served by the routes without a /chains/:chain prefix, defaults to the first chain
+    #[serde(default)]
+    pub default_chain: Option<String>,
+    // Upper bound for a single JSON-RPC request to the provider
+    #[serde(default = "default_rpc_timeout_secs")]
+    pub rpc_timeout_secs: u64,
+    // Retries of a request that failed with a retryable error, 0 disables retrying
+    #[serde(default = "default_rpc_max_retries")]
+    pub rpc_max_retries: u32,
+    // Backoff before the first retry, doubled on every further retry up to rpc_retry_max_delay_ms
+    #[serde(default = "default_rpc_retry_base_delay_ms")]
+    pub rpc_retry_base_delay_ms: u64,
+    #[serde(default = "default_rpc_retry_max_delay_ms")]
+    pub rpc_retry_max_delay_ms: u64,
+    // Consecutive failures after which a provider's circuit opens and it is ejected from rotation
+    #[serde(default = "default_provider_eject_after_failures")]
+    pub provider_eject_after_failures: u32,
+    // How long an open circuit skips its provider before letting a trial request through
+    #[serde(default = "default_circuit_open_secs")]
+    pub circuit_open_secs: u64,
+    // Interval between health probes of ejected providers
+    #[serde(default = "default_provider_probe_interval_secs")]
+    pub provider_probe_interval_secs: u64,
+    // Multicall3 contract used to aggregate read calls, falls back to parallel calls when unset
+    #[serde(default)]
+    pub multicall_address: Option<String>,
+    // ENS registry used to resolve names given in place of addresses, defaults to the canonical registry
+    #[serde(default)]
+    pub ens_registry_address: Option<String>,
+    // Reject mixed-case addresses whose EIP-55 checksum does not match instead of accepting them
+    #[serde(default)]
+    pub strict_address_checksum: bool,
+    // Maximum number of calls packed into a single aggregated eth_call
+    #[serde(default = "default_multicall_batch_size")]
+    pub multicall_batch_size: usize,
+    // Maximum number of in-flight eth_calls per request
+    #[serde(default = "default_max_concurrent_calls")]
+    pub max_concurrent_calls: usize,
+    // Maximum number of blocks covered by a single eth_getLogs request
+    #[serde(default = "default_log_block_range")]
+    pub log_block_range: u64,
+    // Maximum number of blocks a single transfer history request scans before returning a partial page
+    #[serde(default = "default_max_scan_blocks")]
+    pub max_scan_blocks: u64,
+    // Maximum number of address and id pairs read by one ERC-1155 balances request
+    #[serde(default = "default_max_multitoken_pairs")]
+    pub max_multitoken_pairs: usize,
+    // Interval between new block filter polls for live transfer streams
+    #[serde(default = "default_stream_poll_interval_ms")]
+    pub stream_poll_interval_ms: u64,
+    // JSON file mapping user names to their wallet addresses
+    #[serde(default = "default_users_file")]
+    pub users_file: String,
+    // Directory of `<name>.json` ABIs that contract calls can reference by name
+    #[serde(default)]
+    pub abi_dir: Option<String>,
+    // How long token name, symbol and decimals are cached before being read again
+    #[serde(default = "default_token_cache_ttl_secs")]
+    pub token_cache_ttl_secs: u64,
+    // Maximum number of tokens kept in the metadata cache, 0 disables caching
+    #[serde(default = "default_token_cache_max_entries")]
+    pub token_cache_max_entries: usize,
+}
+
+impl EnvConfig {
+    // Configured chains, or a single chain on `provider_url` when the chains table is absent
+    pub fn chains(&self) -> Vec<ChainConfig> {
+        if !self.chains.is_empty() {
+            return self.chains.clone();
+        }
+
+        vec![ChainConfig {
+            name: LEGACY_CHAIN_NAME.to_string(),
+            chain_id: None,
+            provider_urls: vec![self.provider_url.clone()],
+            provider_strategy: ProviderStrategy::default(),
+            quorum: None,
+            multicall_address: None,
+            ens_registry_address: None,
+            token_overrides: Vec::new(),
+        }]
+    }
+
+    pub fn default_chain(&self) -> String {
+        match &self.default_chain {
+            Some(default_chain) => default_chain.clone(),
+            None => self.chains().remove(0).name,
+        }
+    }
+
+    fn validate(&self) -> Result<(), AppServerError> {
+        let chains = self.chains();
+        let mut names = HashSet::new();
+        let mut chain_ids = HashSet::new();
+        for chain in &chains {
+            if chain.provider_urls.is_empty() {
+                return Err(AppServerError::ConfigError(format!("Chain {} has no provider url", chain.name)));
+            }
+            for provider_url in &chain.provider_urls {
+                if let Err(e) = provider_url.parse::<ProviderEndpoint>() {
+                    return Err(AppServerError::ConfigError(format!("Chain {} has an invalid provider url: {}", chain.name, e)));
+                }
+            }
+            if let Some(quorum) = chain.quorum {
+                if quorum == 0 || quorum > chain.provider_urls.len() {
+                    return Err(AppServerError::ConfigError(format!(
+                        "Chain {} quorum {} must be between 1 and its {} provider urls",
+                        chain.name, quorum, chain.provider_urls.len()
+                    )));
+                }
+            }
+            if !names.insert(chain.name.as_str()) {
+                return Err(AppServerError::ConfigError(format!("Duplicate chain name: {}", chain.name)));
+            }
+            if let Some(chain_id) = chain.chain_id {
+                if !chain_ids.insert(chain_id) {
+                    return Err(AppServerError::ConfigError(format!("Duplicate chain id: {}", chain_id)));
+                }
+            }
+        }
+
+        let default_chain = self.default_chain();
+        if !names.contains(default_chain.as_str()) {
+            return Err(AppServerError::ConfigError(format!("Unknown default chain: {}", default_chain)));
+        }
+        Ok(())
+    }
+}
+
+fn default_rpc_timeout_secs() -> u64 {
+    10
+}
+
+fn default_rpc_max_retries() -> u32 {
+    3
+}
+
+fn default_rpc_retry_base_delay_ms() -> u64 {
+    100
+}
+
+fn default_rpc_retry_max_delay_ms() -> u64 {
+    2000
+}
+
+fn default_provider_eject_after_failures() -> u32 {
+    3
+}
+
+fn default_circuit_open_secs() -> u64 {
+    30
+}
+
+fn default_provider_probe_interval_secs() -> u64 {
+    15
+}
+
+fn default_multicall_batch_size() -> usize {
+    100
+}
+
+fn default_max_concurrent_calls() -> usize {
+    10
+}
+
+fn default_log_block_range() -> u64 {
+    5000
+}
+
+fn default_max_scan_blocks() -> u64 {
+    100_000
+}
+
+fn default_max_multitoken_pairs() -> usize {
+    1000
+}
+
+fn default_stream_poll_interval_ms() -> u64 {
+    2000
+}
+
+fn default_users_file() -> String {
+    "./geth/addresses.json".to_string()
+}
+
+fn default_token_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_token_cache_max_entries() -> usize {
+    10000
 }
 
-#[derive(Debug)]
 pub struct ServerConfig {
     pub port: String,
+    // Bearer token required by the /admin routes, which are disabled when it is unset
+    pub admin_token: Option<String>,
+}
+
+// Keeps the admin token out of the startup config log
+impl std::fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("port", &self.port)
+            .field("admin_token", &self.admin_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
 }
 
 #[derive(Debug)]
@@ -20,49 +306,56 @@ pub struct AppConfig {
 static CONFIG: OnceCell<AppConfig> = OnceCell::new();
 
 pub fn get_app_config() -> Result<&'static AppConfig, AppServerError> {
-    CONFIG.get_or_try_init(|| {
-        // Get the env variable
-        let env = env::var("ENV")
-            .map_err(|_| AppServerError::ConfigError("ENV is not set".to_string()))?;
-
-        // Get the port variable
-        let port = env::var("PORT")
-            .map_err(|_| AppServerError::ConfigError("PORT is not set".to_string()))?;
-
-        // Allowed environments
-        let allowed_envs: HashSet<&str> = ["dev", "prd", "stg"].iter().cloned().collect();
-
-        if !allowed_envs.contains(env.as_str()) {
-            return Err(AppServerError::ConfigError(format!(
-                "Invalid environment: {}",
-                env
-            )));
-        }
+    CONFIG.get_or_try_init(|| load_app_config("./src/environment"))
+}
 
-        // Load the TOML configuration file corresponding to env
-        let env_setting = Config::builder()
-            .add_source(File::with_name(&format!("./src/environment/{}", env)))
-            .build()
-            .map_err(|_| {
-                AppServerError::ConfigError(format!(
-                    "Invalid file path: ./src/environment/{}.toml",
-                    env
-                ))
-            })?;
+// Build the app config from the environment variables and the TOML file of ENV in `config_dir`
+fn load_app_config(config_dir: &str) -> Result<AppConfig, AppServerError> {
+    // Get the env variable
+    let env = env::var("ENV")
+        .map_err(|_| AppServerError::ConfigError("ENV is not set".to_string()))?;
+
+    // Get the port variable
+    let port = env::var("PORT")
+        .map_err(|_| AppServerError::ConfigError("PORT is not set".to_string()))?;
+
+    // Get the admin token, read from the environment rather than the committed TOML files
+    let admin_token = env::var("ADMIN_TOKEN").ok().filter(|admin_token| !admin_token.is_empty());
+
+    // Allowed environments
+    let allowed_envs: HashSet<&str> = ["dev", "prd", "stg"].iter().cloned().collect();
+
+    if !allowed_envs.contains(env.as_str()) {
+        return Err(AppServerError::ConfigError(format!(
+            "Invalid environment: {}",
+            env
+        )));
+    }
+
+    // Load the TOML configuration file corresponding to env
+    let env_setting = Config::builder()
+        .add_source(File::with_name(&format!("{}/{}", config_dir, env)))
+        .build()
+        .map_err(|_| {
+            AppServerError::ConfigError(format!(
+                "Invalid file path: {}/{}.toml",
+                config_dir, env
+            ))
+        })?;
 
-        // Deserialize into EnvConfig
-        let env_config: EnvConfig = env_setting
-            .try_deserialize()
-            .map_err(|_| AppServerError::ConfigError("Invalid EnvConfig structure".to_string()))?;
+    // Deserialize into EnvConfig
+    let env_config: EnvConfig = env_setting
+        .try_deserialize()
+        .map_err(|_| AppServerError::ConfigError("Invalid EnvConfig structure".to_string()))?;
+    env_config.validate()?;
 
-        // Build the app config
-        let app_config = AppConfig {
-            server: ServerConfig { port },
-            environment: env_config,
-        };
+    // Build the app config
+    let app_config = AppConfig {
+        server: ServerConfig { port, admin_token },
+        environment: env_config,
+    };
 
-        Ok(app_config)
-    })
+    Ok(app_config)
 }
 
 #[cfg(test)]
@@ -82,19 +375,16 @@ mod tests {
             provider_url = "http://localhost:8080"
         "#;
 
-        // Ensure the directory exists
-        let config_dir = "./src/environment";
-        fs::create_dir_all(config_dir).unwrap();
-
-        // Write the configuration file
-        let config_path = format!("{}/dev.toml", config_dir);
-        fs::write(&config_path, config_content).unwrap();
+        // Write the configuration file to a temporary directory, leaving the tracked environment files alone
+        let config_dir = env::temp_dir().join(format!("blockchain-config-{}", Uuid::new_v4()));
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join("dev.toml"), config_content).unwrap();
 
         // Call the function
-        let result = get_app_config();
+        let result = load_app_config(config_dir.to_str().unwrap());
 
         // Clean up
-        fs::remove_file(&config_path).unwrap();
+        fs::remove_dir_all(&config_dir).unwrap();
         env::remove_var("ENV");
         env::remove_var("PORT");
 
@@ -107,4 +397,103 @@ mod tests {
             "http://localhost:8080"
         );
     }
+
+    #[test]
+    fn test_env_config_optional_fields_default() {
+        let env_setting = Config::builder()
+            .add_source(File::from_str(
+                r#"provider_url = "http://localhost:8545""#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+
+        let env_config: EnvConfig = env_setting.try_deserialize().unwrap();
+
+        assert_eq!(env_config.rpc_timeout_secs, 10);
+        assert_eq!(env_config.rpc_max_retries, 3);
+        assert_eq!(env_config.rpc_retry_base_delay_ms, 100);
+        assert_eq!(env_config.rpc_retry_max_delay_ms, 2000);
+        assert_eq!(env_config.provider_eject_after_failures, 3);
+        assert_eq!(env_config.circuit_open_secs, 30);
+        assert_eq!(env_config.provider_probe_interval_secs, 15);
+        assert_eq!(env_config.multicall_address, None);
+        assert_eq!(env_config.ens_registry_address, None);
+        assert!(!env_config.strict_address_checksum);
+        assert_eq!(env_config.multicall_batch_size, 100);
+        assert_eq!(env_config.max_concurrent_calls, 10);
+        assert_eq!(env_config.log_block_range, 5000);
+        assert_eq!(env_config.max_scan_blocks, 100_000);
+        assert_eq!(env_config.max_multitoken_pairs, 1000);
+        assert_eq!(env_config.stream_poll_interval_ms, 2000);
+        assert_eq!(env_config.users_file, "./geth/addresses.json");
+        assert_eq!(env_config.abi_dir, None);
+        assert_eq!(env_config.token_cache_ttl_secs, 3600);
+        assert_eq!(env_config.token_cache_max_entries, 10000);
+
+        // Without a chains table the provider url serves a single default chain
+        let chains = env_config.chains();
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].provider_urls, vec!["http://localhost:8545".to_string()]);
+        assert_eq!(env_config.default_chain(), chains[0].name);
+        assert!(env_config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_provider_endpoint_from_str() {
+        assert!(matches!("https://mainnet.example.org/v3/key".parse(), Ok(ProviderEndpoint::Http(_))));
+        assert!(matches!("wss://mainnet.example.org/ws".parse(), Ok(ProviderEndpoint::Ws(_))));
+        assert_eq!("/var/run/geth.ipc".parse(), Ok(ProviderEndpoint::Ipc(PathBuf::from("/var/run/geth.ipc"))));
+        assert_eq!("ipc:///tmp/geth.ipc".parse(), Ok(ProviderEndpoint::Ipc(PathBuf::from("/tmp/geth.ipc"))));
+        assert!("ftp://example.org".parse::<ProviderEndpoint>().is_err());
+        assert!("geth-node:8545".parse::<ProviderEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_env_config_chains_table() {
+        let env_setting = Config::builder()
+            .add_source(File::from_str(
+                r#"
+                default_chain = "sepolia"
+
+                [[chains]]
+                name = "mainnet"
+                chain_id = 1
+                provider_urls = ["http://localhost:8545"]
+
+                [[chains]]
+                name = "sepolia"
+                chain_id = 11155111
+                provider_urls = ["http://localhost:8546", "http://localhost:8547", "http://localhost:8548"]
+                provider_strategy = "quorum"
+                quorum = 2
+                multicall_address = "0xcA11bde05977b3631167028862bE2a173976CA11"
+
+                [[chains.token_overrides]]
+                address = "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2"
+                symbol = "MKR"
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+
+        let mut env_config: EnvConfig = env_setting.try_deserialize().unwrap();
+
+        assert!(env_config.validate().is_ok());
+        assert_eq!(env_config.chains().len(), 2);
+        assert_eq!(env_config.default_chain(), "sepolia");
+        assert_eq!(env_config.chains()[1].chain_id, Some(11155111));
+        assert_eq!(env_config.chains()[0].provider_strategy, ProviderStrategy::PrimaryFallback);
+        assert_eq!(env_config.chains()[1].provider_strategy, ProviderStrategy::Quorum);
+        assert_eq!(env_config.chains()[1].token_overrides[0].symbol.as_deref(), Some("MKR"));
+        assert_eq!(env_config.chains()[1].token_overrides[0].decimals, None);
+
+        env_config.chains[1].quorum = Some(4);
+        assert!(env_config.validate().is_err());
+        env_config.chains[1].quorum = Some(2);
+
+        env_config.default_chain = Some("polygon".to_string());
+        assert!(env_config.validate().is_err());
+    }
 }
@@ -1,8 +1,10 @@
 use crate::prelude::*;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::response::Response;
 use ethers::prelude::*;
-use ethers::providers::{Provider, Http};
-use ethers::providers::ProviderError;
+use ethers::providers::{ProviderError, RpcError};
 use ethers::utils::ConversionError;
+use crate::services::upstream::{is_rate_limited, EthProvider, CIRCUIT_OPEN, QUORUM_UNAVAILABLE, REQUEST_TIMED_OUT};
 
 #[derive(Error, Debug)]
 pub enum AppServerError {
@@ -10,8 +12,20 @@ pub enum AppServerError {
     IoError(#[from] io::Error),
     #[error("Configuration error: {0}")]
     ConfigError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Request took too long")]
+    RequestTimeout,
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Admin routes disabled: {0}")]
+    AdminDisabled(String),
+    #[error("{0}")]
+    Internal(String),
     #[error("Blockchain service error: {0}")]
     BlockchainServiceError(#[from] BlockchainServiceError),
+    #[error("User service error: {0}")]
+    UserServiceError(#[from] UserServiceError),
 }
 
 #[derive(Error, Debug)]
@@ -20,7 +34,10 @@ pub enum BlockchainServiceError {
     EthProviderError(#[from] ProviderError),
 
     #[error("Contract error: {0}")]
-    EthContractError(#[from] ContractError<Provider<Http>>),
+    EthContractError(#[from] ContractError<EthProvider>),
+
+    #[error("Multicall error: {0}")]
+    EthMulticallError(#[from] MulticallError<EthProvider>),
 
     #[error("Conversion error: {0}")]
     EthConversionError(#[from] ConversionError),
@@ -28,6 +45,257 @@ pub enum BlockchainServiceError {
     #[error("Invalid address: {0}")]
     InvalidAddress(String),
 
+    #[error("No contract deployed at {0}")]
+    NotAContract(String),
+
+    #[error("Invalid token id: {0}")]
+    InvalidTokenId(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("Request too large: {0}")]
+    RequestTooLarge(String),
+
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+
+    #[error("Invalid contract call: {0}")]
+    InvalidContractCall(String),
+
+    #[error("ABI not found: {0}")]
+    AbiNotFound(String),
+
+    #[error("Undecodable call output: {0}")]
+    InvalidCallOutput(String),
+
+    #[error("Invalid fee data: {0}")]
+    InvalidFeeData(String),
+
+    #[error("Execution reverted: {0}")]
+    ExecutionReverted(String),
+
+    #[error("Transaction not found: {0}")]
+    TransactionNotFound(String),
+
+    #[error("ENS name not found: {0}")]
+    EnsNameNotFound(String),
+
+    #[error("Unknown chain: {0}")]
+    UnknownChain(String),
+
     #[error("Parse error: {0}")]
     ParseError(#[from] std::num::ParseIntError),
-}
\ No newline at end of file
+}
+
+#[derive(Error, Debug)]
+pub enum UserServiceError {
+    #[error("User not found: {0}")]
+    UserNotFound(String),
+
+    #[error("Invalid balance: {0}")]
+    InvalidBalance(String),
+
+    #[error("Conversion error: {0}")]
+    ConversionError(#[from] ConversionError),
+
+    #[error("Blockchain service error: {0}")]
+    BlockchainServiceError(#[from] BlockchainServiceError),
+}
+
+// HTTP status, stable machine-readable code and short title of an error response.
+// Codes are part of the API contract (see ErrorResponse in openapi.yaml), clients may branch on them.
+type ErrorKind = (StatusCode, &'static str, &'static str);
+
+const INVALID_ADDRESS: ErrorKind = (StatusCode::BAD_REQUEST, "invalid_address", "Invalid address");
+const INVALID_REQUEST: ErrorKind = (StatusCode::BAD_REQUEST, "invalid_request", "Invalid request");
+const INVALID_TRANSACTION: ErrorKind = (StatusCode::BAD_REQUEST, "invalid_transaction", "Invalid transaction");
+const UNAUTHORIZED: ErrorKind = (StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized");
+const ADMIN_DISABLED: ErrorKind = (StatusCode::FORBIDDEN, "admin_disabled", "Admin routes disabled");
+const USER_NOT_FOUND: ErrorKind = (StatusCode::NOT_FOUND, "user_not_found", "User not found");
+const ENS_NAME_NOT_FOUND: ErrorKind = (StatusCode::NOT_FOUND, "ens_name_not_found", "ENS name not found");
+const ABI_NOT_FOUND: ErrorKind = (StatusCode::NOT_FOUND, "abi_not_found", "ABI not found");
+const TRANSACTION_NOT_FOUND: ErrorKind = (StatusCode::NOT_FOUND, "transaction_not_found", "Transaction not found");
+const UNKNOWN_CHAIN: ErrorKind = (StatusCode::NOT_FOUND, "unknown_chain", "Unknown chain");
+const NOT_A_CONTRACT: ErrorKind = (StatusCode::NOT_FOUND, "not_a_contract", "Not a contract");
+const REQUEST_TIMEOUT: ErrorKind = (StatusCode::REQUEST_TIMEOUT, "request_timeout", "Request timeout");
+const NOT_ERC20: ErrorKind = (StatusCode::UNPROCESSABLE_ENTITY, "not_erc20", "Not an ERC20 token");
+const CONTRACT_REVERTED: ErrorKind = (StatusCode::UNPROCESSABLE_ENTITY, "contract_reverted", "Contract call reverted");
+const INVALID_CALL_OUTPUT: ErrorKind = (StatusCode::UNPROCESSABLE_ENTITY, "invalid_call_output", "Undecodable call output");
+const INVALID_TOKEN_DATA: ErrorKind = (StatusCode::UNPROCESSABLE_ENTITY, "invalid_token_data", "Invalid token data");
+const INTERNAL_ERROR: ErrorKind = (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "Internal error");
+const UPSTREAM_ERROR: ErrorKind = (StatusCode::BAD_GATEWAY, "upstream_error", "Provider error");
+const UPSTREAM_UNREACHABLE: ErrorKind = (StatusCode::BAD_GATEWAY, "upstream_unreachable", "Provider unreachable");
+const UPSTREAM_RATE_LIMITED: ErrorKind = (StatusCode::SERVICE_UNAVAILABLE, "upstream_rate_limited", "Provider rate limited");
+const UPSTREAM_CIRCUIT_OPEN: ErrorKind = (StatusCode::SERVICE_UNAVAILABLE, "upstream_circuit_open", "Provider circuit open");
+const UPSTREAM_QUORUM_UNAVAILABLE: ErrorKind = (StatusCode::SERVICE_UNAVAILABLE, "upstream_quorum_unavailable", "Provider quorum unavailable");
+const UPSTREAM_TIMEOUT: ErrorKind = (StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", "Provider timeout");
+
+fn error_response((status, code, title): ErrorKind, message: String) -> Response {
+    let response_body = json!({
+        "code": code,
+        "error": title,
+        "message": message,
+    });
+    (status, Json(response_body)).into_response()
+}
+
+fn provider_error_kind(e: &ProviderError) -> ErrorKind {
+    if let ProviderError::CustomError(message) = e {
+        if message.starts_with(CIRCUIT_OPEN) {
+            return UPSTREAM_CIRCUIT_OPEN;
+        }
+        if message.starts_with(QUORUM_UNAVAILABLE) {
+            return UPSTREAM_QUORUM_UNAVAILABLE;
+        }
+        if message.starts_with(REQUEST_TIMED_OUT) {
+            return UPSTREAM_TIMEOUT;
+        }
+    }
+    if let ProviderError::HTTPError(e) = e {
+        return if e.is_timeout() {
+            UPSTREAM_TIMEOUT
+        } else if e.status().is_some_and(|status| status == StatusCode::TOO_MANY_REQUESTS.as_u16()) {
+            UPSTREAM_RATE_LIMITED
+        } else {
+            UPSTREAM_UNREACHABLE
+        };
+    }
+
+    match RpcError::as_error_response(e) {
+        Some(rpc_error) if rpc_error.is_revert() => CONTRACT_REVERTED,
+        Some(rpc_error) if is_rate_limited(rpc_error) => UPSTREAM_RATE_LIMITED,
+        Some(_) => UPSTREAM_ERROR,
+        // Websocket and IPC connection failures, HTTP ones are reported as HTTPError above
+        None if matches!(e, ProviderError::JsonRpcClientError(_)) && RpcError::as_serde_error(e).is_none() => UPSTREAM_UNREACHABLE,
+        None => UPSTREAM_ERROR,
+    }
+}
+
+fn contract_error_kind(e: &ContractError<EthProvider>) -> ErrorKind {
+    match e {
+        ContractError::Revert(_) => CONTRACT_REVERTED,
+        // The address answered with data that does not decode as the ERC20 ABI
+        ContractError::DecodingError(_) | ContractError::DetokenizationError(_) | ContractError::AbiError(_) => NOT_ERC20,
+        ContractError::ContractNotDeployed => NOT_A_CONTRACT,
+        ContractError::MiddlewareError { e } | ContractError::ProviderError { e } => provider_error_kind(e),
+        _ => INTERNAL_ERROR,
+    }
+}
+
+impl BlockchainServiceError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            BlockchainServiceError::InvalidAddress(_) => INVALID_ADDRESS,
+            BlockchainServiceError::ParseError(_) | BlockchainServiceError::InvalidTokenId(_) | BlockchainServiceError::InvalidAmount(_) => INVALID_REQUEST,
+            BlockchainServiceError::RequestTooLarge(_) => INVALID_REQUEST,
+            BlockchainServiceError::InvalidContractCall(_) => INVALID_REQUEST,
+            BlockchainServiceError::AbiNotFound(_) => ABI_NOT_FOUND,
+            BlockchainServiceError::InvalidCallOutput(_) => INVALID_CALL_OUTPUT,
+            BlockchainServiceError::InvalidFeeData(_) => UPSTREAM_ERROR,
+            BlockchainServiceError::NotAContract(_) => NOT_A_CONTRACT,
+            BlockchainServiceError::InvalidTransaction(_) => INVALID_TRANSACTION,
+            BlockchainServiceError::ExecutionReverted(_) => CONTRACT_REVERTED,
+            BlockchainServiceError::TransactionNotFound(_) => TRANSACTION_NOT_FOUND,
+            BlockchainServiceError::UnknownChain(_) => UNKNOWN_CHAIN,
+            BlockchainServiceError::EnsNameNotFound(_) => ENS_NAME_NOT_FOUND,
+            BlockchainServiceError::EthConversionError(_) => INVALID_TOKEN_DATA,
+            BlockchainServiceError::EthProviderError(e) => provider_error_kind(e),
+            BlockchainServiceError::EthContractError(e) => contract_error_kind(e),
+            BlockchainServiceError::EthMulticallError(MulticallError::ContractError(e)) => contract_error_kind(e),
+            BlockchainServiceError::EthMulticallError(MulticallError::IllegalRevert) => CONTRACT_REVERTED,
+            BlockchainServiceError::EthMulticallError(_) => INTERNAL_ERROR,
+        }
+    }
+
+    // Response code, for tests asserting the API contract
+    #[cfg(test)]
+    pub fn code(&self) -> &'static str {
+        self.kind().1
+    }
+
+    // Typed checks for control flow, so renaming a response code does not change fallback behaviour
+    pub fn is_revert(&self) -> bool {
+        self.kind() == CONTRACT_REVERTED
+    }
+
+    pub fn is_not_erc20(&self) -> bool {
+        self.kind() == NOT_ERC20
+    }
+
+    pub fn is_not_a_contract(&self) -> bool {
+        self.kind() == NOT_A_CONTRACT
+    }
+}
+
+// Extractor rejections share the error body of every other invalid request
+impl From<PathRejection> for AppServerError {
+    fn from(rejection: PathRejection) -> Self {
+        AppServerError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppServerError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppServerError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for AppServerError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppServerError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl IntoResponse for BlockchainServiceError {
+    fn into_response(self) -> Response {
+        error_response(self.kind(), self.to_string())
+    }
+}
+
+impl IntoResponse for UserServiceError {
+    fn into_response(self) -> Response {
+        match self {
+            UserServiceError::UserNotFound(user) => error_response(USER_NOT_FOUND, user),
+            UserServiceError::InvalidBalance(_) | UserServiceError::ConversionError(_) => error_response(INVALID_TOKEN_DATA, self.to_string()),
+            UserServiceError::BlockchainServiceError(e) => e.into_response(),
+        }
+    }
+}
+
+impl IntoResponse for AppServerError {
+    fn into_response(self) -> Response {
+        match self {
+            AppServerError::InvalidRequest(message) => error_response(INVALID_REQUEST, message),
+            AppServerError::RequestTimeout => error_response(REQUEST_TIMEOUT, self.to_string()),
+            AppServerError::Unauthorized(message) => error_response(UNAUTHORIZED, message),
+            AppServerError::AdminDisabled(message) => error_response(ADMIN_DISABLED, message),
+            AppServerError::IoError(_) | AppServerError::ConfigError(_) | AppServerError::Internal(_) => error_response(INTERNAL_ERROR, self.to_string()),
+            AppServerError::BlockchainServiceError(e) => e.into_response(),
+            AppServerError::UserServiceError(e) => e.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_status_and_code() {
+        let invalid_address = BlockchainServiceError::InvalidAddress("wallet address [0x12]".to_string());
+        assert_eq!(invalid_address.kind(), INVALID_ADDRESS);
+
+        let reverted = BlockchainServiceError::EthContractError(ContractError::Revert(Bytes::default()));
+        assert_eq!(reverted.kind(), CONTRACT_REVERTED);
+        assert!(reverted.is_revert() && !reverted.is_not_erc20());
+
+        let not_erc20 = BlockchainServiceError::EthMulticallError(MulticallError::ContractError(ContractError::DetokenizationError(ethers::abi::InvalidOutputType("bool".to_string()))));
+        assert_eq!(not_erc20.code(), "not_erc20");
+        assert!(not_erc20.is_not_erc20() && !not_erc20.is_revert());
+        assert!(BlockchainServiceError::NotAContract("0x12".to_string()).is_not_a_contract());
+
+        let timeout = AppServerError::RequestTimeout.into_response();
+        assert_eq!(timeout.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+}
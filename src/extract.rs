@@ -0,0 +1,140 @@
+use crate::error::AppServerError;
+use crate::prelude::*;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::response::Response;
+use ethers::types::BlockId;
+use serde::de::DeserializeOwned;
+use std::convert::Infallible;
+
+// Drop-in replacements for the axum extractors whose rejections answer with the
+// invalid_request error body instead of axum's plain text
+
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
+// Also used for responses, serialized the same way as axum's Json
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppServerError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(request, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+// Chain name or chain id of the /chains/:chain routes, None on the default chain aliases
+pub struct Chain(pub Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Chain {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // The default chain aliases may have no path parameters at all
+        let params = axum::extract::Path::<HashMap<String, String>>::from_request_parts(parts, state).await;
+        Ok(Chain(params.ok().and_then(|axum::extract::Path(mut params)| params.remove("chain"))))
+    }
+}
+
+#[derive(Deserialize)]
+struct BlockParam {
+    // Block number, tag (latest, safe, finalized, pending, earliest) or EIP-1898 block hash
+    block: Option<String>,
+}
+
+// Optional `block` query parameter to read at, None for the latest block
+pub struct Block(pub Option<BlockId>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Block {
+    type Rejection = AppServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(BlockParam { block }) = Query::<BlockParam>::from_request_parts(parts, state).await?;
+        Ok(Block(parse_block(block.as_deref())?))
+    }
+}
+
+// Parse the optional block query parameter, answering 400 when it is neither a number, a tag nor a block hash
+fn parse_block(block: Option<&str>) -> Result<Option<BlockId>, AppServerError> {
+    match block.map(str::trim).filter(|block| !block.is_empty()) {
+        None => Ok(None),
+        Some(block) => block.parse::<BlockId>().map(Some).map_err(|e| {
+            AppServerError::InvalidRequest(format!("block [{}] {}", block, e))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block() {
+        use ethers::types::{BlockNumber, H256};
+
+        assert_eq!(parse_block(None).unwrap(), None);
+        assert_eq!(parse_block(Some(" ")).unwrap(), None);
+        assert_eq!(parse_block(Some("12345")).unwrap(), Some(BlockId::Number(BlockNumber::Number(12345.into()))));
+        assert_eq!(parse_block(Some("0x3039")).unwrap(), Some(BlockId::Number(BlockNumber::Number(12345.into()))));
+        for (tag, block_number) in [
+            ("latest", BlockNumber::Latest),
+            ("safe", BlockNumber::Safe),
+            ("finalized", BlockNumber::Finalized),
+            ("pending", BlockNumber::Pending),
+            ("earliest", BlockNumber::Earliest),
+        ] {
+            assert_eq!(parse_block(Some(tag)).unwrap(), Some(BlockId::Number(block_number)));
+        }
+        // EIP-1898 block hash
+        let hash = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+        assert_eq!(parse_block(Some(hash)).unwrap(), Some(BlockId::Hash(hash.parse::<H256>().unwrap())));
+
+        for block in ["yesterday", "-1", "0xzz", "1.5", &hash[..65]] {
+            assert!(matches!(parse_block(Some(block)), Err(AppServerError::InvalidRequest(_))), "{}", block);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod environment;
 mod error;
+mod extract;
 mod prelude;
 mod router;
 mod services;
@@ -9,6 +10,7 @@ use environment::config::get_app_config;
 use prelude::*;
 use router::AppServiceLayer;
 use services::blockchain_service::BlockchainService;
+use services::user_service::UserService;
 use services::validation_service::ValidationService;
 
 #[tokio::main]
@@ -25,10 +27,20 @@ async fn main() {
     let app_config = get_app_config().unwrap();
     info!("App Config: {:?}", app_config);
 
-    let valiadator = ValidationService::new(BlockchainService::new(app_config));
-    valiadator.validate().await;
+    let blockchain_service = Arc::new(BlockchainService::new(app_config).unwrap());
+    let user_service = Arc::new(UserService::new(app_config).unwrap());
 
-    let app_service_layer = Arc::new(AppServiceLayer::new(BlockchainService::new(app_config)));
+    let valiadator = ValidationService::new(blockchain_service.clone(), user_service.clone());
+    if let Err(e) = valiadator.validate().await {
+        error!("Startup validation failed: {}", e);
+        std::process::exit(1);
+    }
+
+    let app_service_layer = Arc::new(AppServiceLayer::new(
+        blockchain_service,
+        user_service,
+        app_config.server.admin_token.clone(),
+    ));
     let router = router::init_router(app_service_layer);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", app_config.server.port))
@@ -64,16 +76,7 @@ async fn main() {
         }
     });
 
-    // Set up a panic hook to handle uncaught exceptions
-    let shutdown_notify_clone = shutdown_notify.clone();
-    let default_panic = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |panic_info| {
-        error!("Uncaught exception: {}", panic_info);
-        // Notify the server to shut down
-        shutdown_notify_clone.notify_one();
-        // Call the default panic hook
-        default_panic(panic_info);
-    }));
+    set_panic_hook();
 
     // Start the server with graceful shutdown
     axum::serve(listener, router)
@@ -86,4 +89,16 @@ async fn main() {
 async fn wait_for_shutdown(shutdown_notify: Arc<Notify>) {
     shutdown_notify.notified().await;
     info!("Shutdown signal received");
-}
\ No newline at end of file
+}
+
+// Set up a panic hook to log uncaught exceptions.
+// It does not shut the server down, handler panics are answered with a 500 by the router's CatchPanicLayer
+// once the hook returns, and a panicking background task only ends that task.
+fn set_panic_hook() {
+    let default_panic = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        error!("Uncaught exception: {}", panic_info);
+        // Call the default panic hook
+        default_panic(panic_info);
+    }));
+}
@@ -28,16 +28,19 @@ pub use std::sync::Arc;
 pub use std::time::Duration;
 
 // Logging (optional, but useful for API servers)
-pub use tracing::{info, error, Level, Span};
+pub use tracing::{debug, info, warn, error, Level, Span};
 
 // Axum prelude
 pub use axum::{
     body::Body,
     error_handling::HandleErrorLayer,
-    extract::{Path, State, Query},
-    http::{Request as AxumHttpRequest, StatusCode},
-    response::{IntoResponse, Json},
-    routing::get,
+    extract::State,
+    http::{HeaderMap, Request as AxumHttpRequest, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json,
+    },
+    routing::{delete, get, post},
     Router,
 };
 
@@ -45,6 +48,10 @@ pub use tower::{
     timeout::{error::Elapsed, TimeoutLayer},
     BoxError, ServiceBuilder,
 };
+pub use tower_http::catch_panic::CatchPanicLayer;
 pub use tower_http::trace::{DefaultOnResponse, TraceLayer};
 
+// Stream combinators
+pub use futures::stream::StreamExt;
+
 pub use uuid::Uuid;
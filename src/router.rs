@@ -1,22 +1,73 @@
+use crate::domain::contract::ContractCallRequest;
+use crate::domain::transaction::TransactionBuildRequest;
+use crate::domain::transfer::{TransferCursor, TransferQuery};
 use crate::prelude::*;
-use crate::services::blockchain_service::{BlockchainService, BlockchainType};
+use crate::error::{AppServerError, BlockchainServiceError, UserServiceError};
+use crate::extract::{Block, Chain, Json, Path, Query};
+use crate::services::blockchain_service::{is_address_or_ens_name, BlockchainAdapter, BlockchainService, NATIVE_TOKEN_ID};
+use crate::domain::account::AccountBalance;
+use crate::services::user_service::UserService;
+use axum::http::header::{HeaderValue, AUTHORIZATION};
+use axum::middleware::{self, Next};
+use axum::response::Response;
 
 pub struct AppServiceLayer {
     pub blockchain_service: Arc<BlockchainService>,
+    pub user_service: Arc<UserService>,
+    pub admin_token: Option<String>,
 }
 
 impl AppServiceLayer {
-    pub fn new(blockchain_service: BlockchainService) -> Self {
+    pub fn new(blockchain_service: Arc<BlockchainService>, user_service: Arc<UserService>, admin_token: Option<String>) -> Self {
         Self {
-            blockchain_service: Arc::new(blockchain_service),
+            blockchain_service,
+            user_service,
+            admin_token,
         }
     }
 }
 
-pub fn init_router(app_state: Arc<AppServiceLayer>) -> Router {
+// Routes served for every configured chain
+fn chain_routes() -> Router<Arc<AppServiceLayer>> {
     Router::new()
         .route("/token/info/:token_address", get(get_token_info))
         .route("/token/balances/:token_address", get(get_token_balances))
+        .route("/token/allowance/:token_address", get(get_token_allowances))
+        .route("/token/:token_address/estimate-transfer", get(estimate_token_transfer))
+        .route("/native/balances", get(get_native_balances))
+        .route("/users/:user/balances/:token_address", get(get_user_balances))
+        .route("/token/transfers/:token_address", get(get_token_transfers))
+        .route("/token/transfers/:token_address/stream", get(stream_token_transfers))
+        .route("/nft/info/:contract_address", get(get_nft_info))
+        .route("/nft/owners/:contract_address", get(get_nft_owners))
+        .route("/nft/balances/:contract_address", get(get_nft_balances))
+        .route("/nft/token-uri/:contract_address", get(get_nft_token_uris))
+        .route("/multitoken/:contract_address/balances", get(get_multitoken_balances))
+        .route("/multitoken/:contract_address/uri", get(get_multitoken_uris))
+        .route("/contract/:contract_address/kind", get(get_contract_kind))
+        .route("/contract/:contract_address/call", post(call_contract))
+        .route("/tx/raw", post(send_raw_transaction))
+        .route("/tx/build", post(build_transaction))
+        .route("/tx/:hash", get(get_transaction))
+}
+
+// Routes that inspect or change server state, only reachable with the admin token
+fn admin_routes(admin_token: Option<String>) -> Router<Arc<AppServiceLayer>> {
+    Router::new()
+        .route("/admin/cache/tokens", get(get_token_cache_stats).delete(evict_token_cache))
+        .route("/admin/cache/tokens/:chain", delete(evict_chain_token_cache))
+        .route("/admin/cache/tokens/:chain/:token_address", delete(evict_token_cache_entry))
+        .route_layer(middleware::from_fn_with_state(admin_token.map(Arc::<str>::from), require_admin_token))
+}
+
+pub fn init_router(app_state: Arc<AppServiceLayer>) -> Router {
+    Router::new()
+        // Unprefixed routes are aliases for the default chain
+        .merge(chain_routes())
+        .nest("/chains/:chain", chain_routes())
+        .route("/chains", get(get_chains))
+        .route("/health", get(get_health))
+        .merge(admin_routes(app_state.admin_token.clone()))
         .with_state(app_state)
         .layer(
         ServiceBuilder::new()
@@ -33,7 +84,11 @@ pub fn init_router(app_state: Arc<AppServiceLayer>) -> Router {
                     })
                     .on_request(|request: &AxumHttpRequest<_>, span: &Span| {
                         let method = request.method();
-                        let headers = request.headers();
+                        let mut headers = request.headers().clone();
+                        // Never log the admin token
+                        if headers.contains_key(AUTHORIZATION) {
+                            headers.insert(AUTHORIZATION, HeaderValue::from_static("<redacted>"));
+                        }
                         let uri = request.uri();
                         let query = uri.query().unwrap_or("NONE");
                         info!(parent: span, "methods={}, uri={}, params={} headers={:?}", method, uri, query, headers);
@@ -41,101 +96,772 @@ pub fn init_router(app_state: Arc<AppServiceLayer>) -> Router {
                     .on_response(DefaultOnResponse::new().level(Level::INFO)), // Response logging
 
             )
+            // A panicking handler answers 500 instead of dropping the connection
+            .layer(CatchPanicLayer::custom(handle_panic))
         )
 }
 
-// Handler for GET /token/info
-async fn get_token_info(
-    Path(token_address): Path<String>,
+#[derive(Debug, Deserialize)]
+struct TokenPath {
+    token_address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContractPath {
+    contract_address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionPath {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserTokenPath {
+    user: String,
+    token_address: String,
+}
+
+// Handler for GET /chains
+async fn get_chains(
     State(app_state): State<Arc<AppServiceLayer>>,
 ) -> impl IntoResponse {
-    if token_address.is_empty() {
-        let response_body  = json!({
-            "error": "Invalid token address",
-            "message": "empty string",
-        });
-        return (StatusCode::BAD_REQUEST, Json(response_body)).into_response();
-    }
-
-    let result = app_state
-        .blockchain_service
-        .get_blockchain_client(BlockchainType::Ethereum)
-        .get_token_by_address(&token_address).await;
-
-    match result {
-        Ok(token_info) => {
-            Json(token_info).into_response()  // Return the token info as JSON
-        }
-        Err(e) => {
-            error!("Error fetching token info: {:?}", e);
-            let response_body  = json!({
-                "error": "Blockchain service error",
-                "message": format!("{:?}", e),
-            });
-            (StatusCode::UNPROCESSABLE_ENTITY, Json(response_body)).into_response()
-        }
-    }
+    Json(app_state.blockchain_service.chains())
 }
 
+// Handler for GET /health
+async fn get_health(
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> impl IntoResponse {
+    let chains = app_state.blockchain_service.health();
+    // Degraded while any chain has no healthy upstream left
+    let status = if chains.iter().all(|chain| chain.healthy) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(json!({ "chains": chains })))
+}
+
+// Handler for GET /token/info
+async fn get_token_info(
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("token", &token_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let token_info = blockchain_client
+        .get_token_by_address(&token_address, block)
+        .await
+        .map_err(logged("fetching token info"))?;
+    Ok(Json(token_info))
+}
 
 #[derive(Debug, Deserialize)]
 struct AddressQuery {
     addresses: String,
+    // Include each address's percentage of the token total supply
+    #[serde(default)]
+    include_share: bool,
+    // Include the ENS primary name of each address
+    #[serde(default)]
+    include_names: bool,
 }
 
 // Handler for GET /token/balances/:token_address
 async fn get_token_balances(
-    Path(token_address): Path<String>,
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
     Query(params): Query<AddressQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    // The reserved native token id reads the chain currency balance instead of an ERC20 contract
+    let is_native = token_address.eq_ignore_ascii_case(NATIVE_TOKEN_ID);
+    if !is_native {
+        require_address("token", &token_address)?;
+    }
+    if is_native && params.include_share {
+        return Err(AppServerError::InvalidRequest("include_share is not supported for the native token, it has no total supply".to_string()));
+    }
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let vec_addresses = split_list(&params.addresses);
+    let mut balances = if is_native {
+        blockchain_client.get_native_balance(vec_addresses, block).await
+    } else {
+        blockchain_client.get_account_balance(&token_address, vec_addresses, params.include_share, block).await
+    }
+    .map_err(logged("fetching token balances"))?;
+    if params.include_names {
+        balances = with_names(blockchain_client, balances, block).await.map_err(logged("fetching token balances"))?;
+    }
+    Ok(Json(balances))
+}
+
+#[derive(Debug, Deserialize)]
+struct NativeBalanceQuery {
+    addresses: String,
+    // Include the ENS primary name of each address
+    #[serde(default)]
+    include_names: bool,
+}
+
+// Handler for GET /native/balances
+async fn get_native_balances(
+    Chain(chain): Chain,
+    Query(params): Query<NativeBalanceQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let mut balances = blockchain_client
+        .get_native_balance(split_list(&params.addresses), block)
+        .await
+        .map_err(logged("fetching native balances"))?;
+    if params.include_names {
+        balances = with_names(blockchain_client, balances, block).await.map_err(logged("fetching native balances"))?;
+    }
+    Ok(Json(balances))
+}
+
+const DEFAULT_TRANSFER_PAGE_SIZE: usize = 100;
+const MAX_TRANSFER_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct TransferHistoryQuery {
+    address: Option<String>,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+    // Opaque `next_cursor` from the previous page
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+// Handler for GET /token/transfers/:token_address
+async fn get_token_transfers(
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
+    Query(params): Query<TransferHistoryQuery>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("token", &token_address)?;
+    if let (Some(from_block), Some(to_block)) = (params.from_block, params.to_block) {
+        if from_block > to_block {
+            return Err(AppServerError::InvalidRequest(format!("from_block [{}] is after to_block [{}]", from_block, to_block)));
+        }
+    }
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(str::parse::<TransferCursor>)
+        .transpose()
+        .map_err(|e| AppServerError::InvalidRequest(format!("cursor {}", e)))?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let query = TransferQuery {
+        address: params.address.map(|address| address.trim().to_string()).filter(|address| !address.is_empty()),
+        from_block: params.from_block,
+        to_block: params.to_block,
+        cursor,
+        limit: params.limit.unwrap_or(DEFAULT_TRANSFER_PAGE_SIZE).clamp(1, MAX_TRANSFER_PAGE_SIZE),
+    };
+    let transfer_page = blockchain_client
+        .get_transfers(&token_address, query)
+        .await
+        .map_err(logged("fetching token transfers"))?;
+    Ok(Json(transfer_page))
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferStreamQuery {
+    // Comma-separated addresses, only transfers sent or received by one of them are pushed
+    addresses: Option<String>,
+}
+
+// Handler for GET /token/transfers/:token_address/stream
+async fn stream_token_transfers(
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
+    Query(params): Query<TransferStreamQuery>,
+    headers: HeaderMap,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("token", &token_address)?;
+    // Reconnecting EventSource clients send the id of the last event they received
+    let resume_after = headers
+        .get("last-event-id")
+        .map(|value| value.to_str().map_err(|e| e.to_string()).and_then(str::parse::<TransferCursor>))
+        .transpose()
+        .map_err(|e| AppServerError::InvalidRequest(format!("Last-Event-ID {}", e)))?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let vec_addresses = params.addresses.as_deref().map(split_list).unwrap_or_default();
+    let transfers = blockchain_client
+        .stream_transfers(&token_address, vec_addresses, resume_after)
+        .await
+        .map_err(logged("subscribing to token transfers"))?;
+    let events = transfers.map(|transfer| {
+        Event::default()
+            .id(transfer.cursor().to_string())
+            .event("transfer")
+            .json_data(&transfer)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Handler for GET /users/:user/balances/:token_address
+async fn get_user_balances(
+    Chain(chain): Chain,
+    Path(UserTokenPath { user, token_address }): Path<UserTokenPath>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("token", &token_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let portfolio = match app_state.user_service.get_user_portfolio(blockchain_client, &user, &token_address, block).await {
+        // An unknown user is the caller's mistake, not worth an error log
+        Err(e @ UserServiceError::UserNotFound(_)) => return Err(e.into()),
+        result => result.map_err(logged("fetching user balances"))?,
+    };
+    Ok(Json(portfolio))
+}
+
+#[derive(Debug, Deserialize)]
+struct AllowanceQuery {
+    owner: String,
+    spenders: String,
+}
+
+// Handler for GET /token/allowance/:token_address
+async fn get_token_allowances(
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
+    Query(params): Query<AllowanceQuery>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("token", &token_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let allowances = blockchain_client
+        .get_allowances(&token_address, params.owner.trim(), split_list(&params.spenders))
+        .await
+        .map_err(logged("fetching token allowances"))?;
+    Ok(Json(allowances))
+}
+
+#[derive(Debug, Deserialize)]
+struct EstimateTransferQuery {
+    from: String,
+    to: String,
+    // Human-readable amount, converted with the token decimals
+    amount: String,
+}
+
+// Handler for GET /token/:token_address/estimate-transfer
+async fn estimate_token_transfer(
+    Chain(chain): Chain,
+    Path(TokenPath { token_address }): Path<TokenPath>,
+    Query(params): Query<EstimateTransferQuery>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    for (kind, address) in [("token", &token_address), ("from", &params.from), ("to", &params.to)] {
+        require_address(kind, address.trim())?;
+    }
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let estimate = blockchain_client
+        .estimate_transfer(&token_address, params.from.trim(), params.to.trim(), &params.amount)
+        .await
+        .map_err(logged("estimating token transfer"))?;
+    Ok(Json(estimate))
+}
+
+// Handler for GET /nft/info/:contract_address
+async fn get_nft_info(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let collection = blockchain_client
+        .get_nft_collection(&contract_address, block)
+        .await
+        .map_err(logged("fetching NFT collection info"))?;
+    Ok(Json(collection))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenIdQuery {
+    // Comma-separated token ids, in decimal or 0x-prefixed hex
+    ids: String,
+}
+
+// Handler for GET /nft/owners/:contract_address
+async fn get_nft_owners(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Query(params): Query<TokenIdQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let owners = blockchain_client
+        .get_nft_owners(&contract_address, split_list(&params.ids), block)
+        .await
+        .map_err(logged("fetching NFT owners"))?;
+    Ok(Json(owners))
+}
+
+#[derive(Debug, Deserialize)]
+struct NftBalanceQuery {
+    addresses: String,
+}
+
+// Handler for GET /nft/balances/:contract_address
+async fn get_nft_balances(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Query(params): Query<NftBalanceQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let balances = blockchain_client
+        .get_nft_balances(&contract_address, split_list(&params.addresses), block)
+        .await
+        .map_err(logged("fetching NFT balances"))?;
+    Ok(Json(balances))
+}
+
+// Handler for GET /nft/token-uri/:contract_address
+async fn get_nft_token_uris(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Query(params): Query<TokenIdQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let token_uris = blockchain_client
+        .get_nft_token_uris(&contract_address, split_list(&params.ids), block)
+        .await
+        .map_err(logged("fetching NFT token URIs"))?;
+    Ok(Json(token_uris))
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiTokenBalanceQuery {
+    addresses: String,
+    // Comma-separated token ids, in decimal or 0x-prefixed hex
+    ids: String,
+}
+
+// Handler for GET /multitoken/:contract_address/balances
+async fn get_multitoken_balances(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Query(params): Query<MultiTokenBalanceQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let balances = blockchain_client
+        .get_multitoken_balances(&contract_address, split_list(&params.addresses), split_list(&params.ids), block)
+        .await
+        .map_err(logged("fetching multi-token balances"))?;
+    Ok(Json(balances))
+}
+
+// Handler for GET /multitoken/:contract_address/uri
+async fn get_multitoken_uris(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Query(params): Query<TokenIdQuery>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let uris = blockchain_client
+        .get_multitoken_uris(&contract_address, split_list(&params.ids), block)
+        .await
+        .map_err(logged("fetching multi-token URIs"))?;
+    Ok(Json(uris))
+}
+
+// Handler for GET /contract/:contract_address/kind
+async fn get_contract_kind(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let contract_kind = blockchain_client
+        .get_contract_kind(&contract_address, block)
+        .await
+        .map_err(logged("detecting contract kind"))?;
+    Ok(Json(contract_kind))
+}
+
+// Handler for POST /contract/:contract_address/call
+async fn call_contract(
+    Chain(chain): Chain,
+    Path(ContractPath { contract_address }): Path<ContractPath>,
+    Block(block): Block,
+    State(app_state): State<Arc<AppServiceLayer>>,
+    Json(request): Json<ContractCallRequest>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("contract", &contract_address)?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let call_result = blockchain_client
+        .call_contract(&contract_address, request, block)
+        .await
+        .map_err(logged("calling contract"))?;
+    Ok(Json(call_result))
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTransactionRequest {
+    // 0x-prefixed RLP of a signed legacy, EIP-2930 or EIP-1559 transaction
+    raw_transaction: String,
+}
+
+// Handler for POST /tx/raw
+async fn send_raw_transaction(
+    Chain(chain): Chain,
+    State(app_state): State<Arc<AppServiceLayer>>,
+    Json(request): Json<RawTransactionRequest>,
+) -> Result<impl IntoResponse, AppServerError> {
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let transaction = blockchain_client
+        .send_raw_transaction(&request.raw_transaction)
+        .await
+        .map_err(logged("broadcasting raw transaction"))?;
+    Ok(Json(transaction))
+}
+
+// Handler for POST /tx/build
+async fn build_transaction(
+    Chain(chain): Chain,
+    State(app_state): State<Arc<AppServiceLayer>>,
+    Json(request): Json<TransactionBuildRequest>,
+) -> Result<impl IntoResponse, AppServerError> {
+    require_address("from", request.from.trim())?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let transaction = blockchain_client
+        .build_transaction(request)
+        .await
+        .map_err(logged("building transaction"))?;
+    Ok(Json(transaction))
+}
+
+// Handler for GET /tx/:hash
+async fn get_transaction(
+    Chain(chain): Chain,
+    Path(TransactionPath { hash }): Path<TransactionPath>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    let hash = hash
+        .parse::<ethers::types::H256>()
+        .map_err(|e| AppServerError::InvalidRequest(format!("transaction hash [{}] {}", hash, e)))?;
+    let blockchain_client = app_state.blockchain_service.get_blockchain_client(chain.as_deref())?;
+
+    let transaction = blockchain_client
+        .get_transaction(hash)
+        .await
+        .map_err(logged("fetching transaction"))?;
+    Ok(Json(transaction))
+}
+
+// Let a request through only with `Authorization: Bearer <admin token>`, refuse all of them when no token is configured
+async fn require_admin_token(
+    State(admin_token): State<Option<Arc<str>>>,
+    request: AxumHttpRequest<Body>,
+    next: Next,
+) -> Response {
+    let Some(admin_token) = admin_token else {
+        return AppServerError::AdminDisabled("set ADMIN_TOKEN to enable them".to_string()).into_response();
+    };
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if tokens_match(token.trim(), &admin_token) => next.run(request).await,
+        Some(_) => AppServerError::Unauthorized("invalid admin token".to_string()).into_response(),
+        None => AppServerError::Unauthorized("missing Authorization: Bearer admin token".to_string()).into_response(),
+    }
+}
+
+// Compare without returning at the first differing byte, so timing does not leak the token prefix
+fn tokens_match(token: &str, admin_token: &str) -> bool {
+    token.len() == admin_token.len() && token.bytes().zip(admin_token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Handler for GET /admin/cache/tokens
+async fn get_token_cache_stats(
     State(app_state): State<Arc<AppServiceLayer>>,
 ) -> impl IntoResponse {
-    if token_address.is_empty() || token_address.parse::<ethers::types::Address>().is_err() {
-        let response_body  = json!({
-            "error": "Invalid token address",
-            "message": "empty string",
-        });
-        return (StatusCode::BAD_REQUEST, Json(response_body)).into_response();
+    Json(app_state.blockchain_service.token_cache().stats())
+}
+
+// Handler for DELETE /admin/cache/tokens
+async fn evict_token_cache(
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> impl IntoResponse {
+    let evicted = app_state.blockchain_service.token_cache().clear();
+    Json(json!({ "evicted": evicted }))
+}
+
+// Handler for DELETE /admin/cache/tokens/:chain
+async fn evict_chain_token_cache(
+    Path(chain): Path<String>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    let chain = app_state.blockchain_service.chain_name(&chain)?;
+
+    let evicted = app_state.blockchain_service.token_cache().evict(chain, None);
+    Ok(Json(json!({ "evicted": evicted })))
+}
+
+// Handler for DELETE /admin/cache/tokens/:chain/:token_address
+async fn evict_token_cache_entry(
+    Path((chain, token_address)): Path<(String, String)>,
+    State(app_state): State<Arc<AppServiceLayer>>,
+) -> Result<impl IntoResponse, AppServerError> {
+    let chain = app_state.blockchain_service.chain_name(&chain)?;
+    let token_address = token_address
+        .parse::<ethers::types::Address>()
+        .map_err(|e| BlockchainServiceError::InvalidAddress(format!("token address [{}] {:?}", token_address, e)))?;
+
+    let evicted = app_state.blockchain_service.token_cache().evict(chain, Some(token_address));
+    Ok(Json(json!({ "evicted": evicted })))
+}
+
+// Answer 400 before reaching any upstream when a parameter is neither an address nor an ENS name
+fn require_address(kind: &str, address: &str) -> Result<(), BlockchainServiceError> {
+    if is_address_or_ens_name(address) {
+        Ok(())
+    } else {
+        Err(BlockchainServiceError::InvalidAddress(format!("{} address [{}]", kind, address)))
+    }
+}
+
+// Log why a request failed, then answer with the error
+fn logged<E>(action: &'static str) -> impl FnOnce(E) -> AppServerError
+where
+    E: std::fmt::Debug + Into<AppServerError>,
+{
+    move |e| {
+        error!("Error {}: {:?}", action, e);
+        e.into()
     }
+}
 
-    let vec_addresses = params.addresses
+// Add the reverse-resolved ENS name of every balance address
+async fn with_names(blockchain_client: &dyn BlockchainAdapter, balances: Vec<AccountBalance>, block: Option<ethers::types::BlockId>) -> Result<Vec<AccountBalance>, BlockchainServiceError> {
+    let addresses = balances.iter().map(|balance| balance.address.clone()).collect();
+    let names = blockchain_client.lookup_names(addresses, block).await?;
+    Ok(balances.into_iter().zip(names).map(|(balance, name)| balance.with_name(name)).collect())
+}
+
+// Split a comma-separated list of addresses or ids, dropping empty entries
+fn split_list(addresses: &str) -> Vec<String> {
+    addresses
         .split(',')
         .map(|s| s.trim().to_string())
         .filter(|s| !s.is_empty())
-        .collect();
-
-    // Call the blockchain facade to get the token balances for the valid addresses
-    let result = app_state
-        .blockchain_service
-        .get_blockchain_client(BlockchainType::Ethereum)
-        .get_account_balance(&token_address, vec_addresses)
-        .await;
-
-    match result {
-        Ok(balances) => {
-            // Return the balances as JSON
-            Json(balances).into_response()
-        }
-        Err(e) => {
-            error!("Error fetching token balances: {:?}", e);
-            let response_body  = json!({
-                "error": "Blockchain service error",
-                "message": format!("{:?}", e),
-            });
-            (StatusCode::UNPROCESSABLE_ENTITY, Json(response_body)).into_response()
-        }
-    }
+        .collect()
+}
+
+fn handle_panic(panic: Box<dyn std::any::Any + Send + 'static>) -> axum::response::Response {
+    let details = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    error!("Request handler panicked: {}", details);
+    AppServerError::Internal("Request handler panicked".to_string()).into_response()
 }
 
-async fn handle_timeout_error(err: BoxError) -> (StatusCode, String) {
+async fn handle_timeout_error(err: BoxError) -> AppServerError {
     if err.is::<Elapsed>() {
-        (
-            StatusCode::REQUEST_TIMEOUT,
-            "Request took too long".to_string(),
-        )
+        AppServerError::RequestTimeout
     } else {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Unhandled internal error: {err}"),
-        )
+        AppServerError::Internal(format!("Unhandled internal error: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::config::{AppConfig, ServerConfig};
+    use crate::services::test_support::{self, env_config};
+    use tower::ServiceExt;
+
+    async fn panicking_handler() -> StatusCode {
+        panic!("resolver returned garbage")
+    }
+
+    // Serve a node that answers eth_getBalance with 4 ether for every address
+    async fn stub_node() -> String {
+        test_support::stub_node(|method, _| match method {
+            "eth_getBalance" => Ok(json!("0x3782dace9d900000")),
+            method => panic!("unexpected {}", method),
+        })
+        .await
+    }
+
+    fn app(provider_url: &str) -> Router {
+        let environment = env_config(&format!("provider_url = \"{}\"\nrpc_max_retries = 0", provider_url));
+        let app_config = AppConfig { server: ServerConfig { port: "0".to_string(), admin_token: None }, environment };
+        let user_service = Arc::new(UserService::new(&app_config).unwrap());
+        init_router(Arc::new(AppServiceLayer::new(Arc::new(BlockchainService::new(&app_config).unwrap()), user_service, None)))
+    }
+
+    async fn get_json(router: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = AxumHttpRequest::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, from_str(std::str::from_utf8(&body).unwrap()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_answers_internal_error() {
+        let router = Router::new()
+            .route("/panic", get(panicking_handler))
+            .layer(CatchPanicLayer::custom(handle_panic));
+
+        let request = AxumHttpRequest::builder().uri("/panic").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(from_str::<serde_json::Value>(std::str::from_utf8(&body).unwrap()).unwrap()["code"], "internal_error");
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_leaves_server_serving() {
+        crate::set_panic_hook();
+        let router = Router::new()
+            .route("/panic", get(panicking_handler))
+            .route("/ok", get(|| async { StatusCode::OK }))
+            .layer(CatchPanicLayer::custom(handle_panic));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        let shutdown_notify = Arc::new(Notify::new());
+        let wait_for_shutdown = crate::wait_for_shutdown(shutdown_notify.clone());
+        let server = tokio::spawn(async move { axum::serve(listener, router).with_graceful_shutdown(wait_for_shutdown).await });
+
+        let http = reqwest::Client::new();
+        let response = http.get(format!("{}/panic", server_url)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+        let response = http.get(format!("{}/ok", server_url)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(!server.is_finished());
+
+        shutdown_notify.notify_one();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_native_token_balances() {
+        let router = app(&stub_node().await);
+        let addresses = "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe,0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2";
+
+        for uri in [format!("/token/balances/native?addresses={}", addresses), format!("/native/balances?addresses={}", addresses)] {
+            let (status, body) = get_json(&router, &uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(body.as_array().unwrap().len(), 2, "{}", uri);
+            assert_eq!(body[1]["address"], "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2", "{}", uri);
+            assert_eq!(body[1]["balance"], "4.000000000000000000", "{}", uri);
+            assert_eq!(body[1]["balance_raw"], "4000000000000000000", "{}", uri);
+        }
+
+        // The native token has no total supply to take a share of
+        let (status, body) = get_json(&router, &format!("/token/balances/NATIVE?addresses={}&include_share=true", addresses)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_require_token() {
+        let admin_router = |admin_token: Option<&str>| {
+            Router::new()
+                .route("/admin/ping", delete(|| async { StatusCode::OK }))
+                .route_layer(middleware::from_fn_with_state(admin_token.map(Arc::<str>::from), require_admin_token))
+        };
+        let request = |authorization: Option<&str>| {
+            let mut request = AxumHttpRequest::builder().method("DELETE").uri("/admin/ping");
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let cases = [
+            (Some("s3cret"), Some("Bearer s3cret"), StatusCode::OK),
+            (Some("s3cret"), Some("Bearer s3cre"), StatusCode::UNAUTHORIZED),
+            (Some("s3cret"), Some("s3cret"), StatusCode::UNAUTHORIZED),
+            (Some("s3cret"), None, StatusCode::UNAUTHORIZED),
+            (None, Some("Bearer s3cret"), StatusCode::FORBIDDEN),
+        ];
+        for (admin_token, authorization, status) in cases {
+            let response = admin_router(admin_token).oneshot(request(authorization)).await.unwrap();
+            assert_eq!(response.status(), status, "{:?} {:?}", admin_token, authorization);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extractor_rejections_answer_invalid_request() {
+        let router = Router::new()
+            .route("/query", get(|Query(params): Query<TransferHistoryQuery>| async move { params.limit.unwrap_or_default().to_string() }))
+            .route("/block", get(|Block(block): Block| async move { format!("{:?}", block) }))
+            .route("/path/:number", get(|Path(number): Path<u64>| async move { number.to_string() }))
+            .route("/json", post(|Json(request): Json<RawTransactionRequest>| async move { request.raw_transaction.to_string() }));
+
+        let requests = [
+            AxumHttpRequest::builder().uri("/query?limit=ten").body(Body::empty()).unwrap(),
+            AxumHttpRequest::builder().uri("/block?block=yesterday").body(Body::empty()).unwrap(),
+            AxumHttpRequest::builder().uri("/block?block=1&block=2").body(Body::empty()).unwrap(),
+            AxumHttpRequest::builder().uri("/path/latest").body(Body::empty()).unwrap(),
+            AxumHttpRequest::builder().method("POST").uri("/json").header("content-type", "application/json").body(Body::from("{}")).unwrap(),
+            AxumHttpRequest::builder().method("POST").uri("/json").body(Body::from("{}")).unwrap(),
+        ];
+        for request in requests {
+            let uri = request.uri().clone();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = from_str::<serde_json::Value>(std::str::from_utf8(&body).unwrap()).unwrap();
+            assert_eq!(body["code"], "invalid_request", "{}", uri);
+            assert!(body["message"].is_string(), "{}", uri);
+        }
     }
 }
@@ -0,0 +1,97 @@
+use ethers::abi::Abi;
+use crate::error::AppServerError;
+use crate::prelude::*;
+
+// ABIs bundled with the server, always available by name
+const BUNDLED_ABIS: [(&str, &str); 3] = [
+    ("erc20", include_str!("../abi/erc20.json")),
+    ("erc721", include_str!("../abi/erc721.json")),
+    ("erc1155", include_str!("../abi/erc1155.json")),
+];
+
+// Named ABIs that contract calls can reference instead of sending the ABI along.
+// Loaded once at startup from the bundled ABIs and the `*.json` files of the configured directory.
+pub struct AbiRegistry {
+    abis: HashMap<String, Arc<Abi>>,
+}
+
+impl AbiRegistry {
+    pub fn load(abi_dir: Option<&str>) -> Result<Self, AppServerError> {
+        let mut abis = HashMap::new();
+        for (name, abi) in BUNDLED_ABIS {
+            let abi = parse_abi_json(abi).map_err(|e| AppServerError::Internal(format!("Bundled ABI {} is invalid: {}", name, e)))?;
+            abis.insert(name.to_string(), Arc::new(abi));
+        }
+
+        if let Some(abi_dir) = abi_dir {
+            let entries = fs::read_dir(abi_dir).map_err(|e| AppServerError::ConfigError(format!("Cannot read ABI directory {}: {}", abi_dir, e)))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                    continue;
+                }
+                let (name, abi) = load_abi_file(&path)?;
+                // A stored ABI with a bundled name replaces the bundled one
+                abis.insert(name, Arc::new(abi));
+            }
+        }
+        info!("Loaded {} contract ABIs", abis.len());
+
+        Ok(AbiRegistry { abis })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Abi>> {
+        self.abis.get(name).cloned()
+    }
+}
+
+// Stored under the file name without extension
+fn load_abi_file(path: &std::path::Path) -> Result<(String, Abi), AppServerError> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| AppServerError::ConfigError(format!("ABI file {} has no valid name", path.display())))?;
+    let abi = parse_abi_json(&fs::read_to_string(path)?)
+        .map_err(|e| AppServerError::ConfigError(format!("ABI file {} is invalid: {}", path.display(), e)))?;
+    Ok((name.to_string(), abi))
+}
+
+// A plain ABI array, or a Hardhat/Truffle artifact carrying it under `abi`
+fn parse_abi_json(json: &str) -> Result<Abi, serde_json::Error> {
+    let value: serde_json::Value = from_str(json)?;
+    parse_abi_value(value)
+}
+
+pub fn parse_abi_value(value: serde_json::Value) -> Result<Abi, serde_json::Error> {
+    match value {
+        serde_json::Value::Object(mut artifact) if artifact.contains_key("abi") => serde_json::from_value(artifact.remove("abi").unwrap_or_default()),
+        // A single fragment such as one function definition
+        fragment @ serde_json::Value::Object(_) => serde_json::from_value(serde_json::Value::Array(vec![fragment])),
+        abi => serde_json::from_value(abi),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_abi_value() {
+        let fragment = json!({
+            "type": "function",
+            "name": "balanceOf",
+            "stateMutability": "view",
+            "inputs": [{ "name": "owner", "type": "address" }],
+            "outputs": [{ "name": "", "type": "uint256" }],
+        });
+        let abi = parse_abi_value(fragment.clone()).unwrap();
+        assert_eq!(abi.functions_by_name("balanceOf").unwrap().len(), 1);
+        assert_eq!(parse_abi_value(json!([fragment.clone()])).unwrap(), abi);
+        assert_eq!(parse_abi_value(json!({ "contractName": "Token", "abi": [fragment] })).unwrap(), abi);
+        assert!(parse_abi_value(json!("balanceOf")).is_err());
+
+        let registry = AbiRegistry::load(None).unwrap();
+        assert!(registry.get("erc20").unwrap().function("transfer").is_ok());
+        assert!(registry.get("unknown").is_none());
+    }
+}
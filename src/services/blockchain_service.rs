@@ -1,39 +1,189 @@
 use crate::environment::config::AppConfig;
-use crate::error::BlockchainServiceError;
+use crate::error::{AppServerError, BlockchainServiceError};
 use crate::prelude::*;
 use crate::services::ethereum::EthereumClient;
+use crate::services::token_cache::{TokenMetadata, TokenMetadataCache};
+use crate::services::upstream::UpstreamStatus;
 use crate::domain::token::TokenInfo;
 use crate::domain::account::AccountBalance;
+use crate::domain::allowance::Allowance;
+use crate::domain::nft::{NftBalance, NftCollection, NftOwner, NftTokenUri};
+use crate::domain::multitoken::{MultiTokenBalance, MultiTokenUri};
+use crate::domain::contract::ContractKind;
+use crate::domain::transaction::{BroadcastTransaction, TransactionBuildRequest, TransactionDetails, UnsignedTransaction};
+use crate::domain::fee::TransferEstimate;
+use crate::domain::contract::{ContractCallRequest, ContractCallResult};
+use crate::services::abi_registry::AbiRegistry;
+use crate::domain::transfer::{TokenTransfer, TransferCursor, TransferPage, TransferQuery};
+use futures::stream::BoxStream;
+use ethers::types::{Address, BlockId, H256};
 
-pub enum BlockchainType {
-    Ethereum,
+// Reserved token id that selects the chain's native currency instead of an ERC20 contract
+pub const NATIVE_TOKEN_ID: &str = "native";
+
+// Whether an address input is a hex address or looks like an ENS name (dot separated labels such as vitalik.eth).
+// Whether a name actually resolves is only known once the chain's ENS registry has been asked.
+pub fn is_address_or_ens_name(input: &str) -> bool {
+    if input.parse::<Address>().is_ok() {
+        return true;
+    }
+    input.contains('.') && input.split('.').all(|label| !label.is_empty() && !label.chars().any(char::is_whitespace))
 }
 
 // Define a common interface for blockchain services
 #[async_trait]
-pub trait BlockchainAdapter {
-    async fn get_token_by_address(&self, token_address: &str) -> Result<TokenInfo, BlockchainServiceError>;
-    async fn get_account_balance(&self, token_address: &str, wallet_addresses:Vec<String>) -> Result<Vec<AccountBalance>, BlockchainServiceError>;
+pub trait BlockchainAdapter: Send + Sync {
+    async fn get_token_by_address(&self, token_address: &str, block: Option<BlockId>) -> Result<TokenInfo, BlockchainServiceError>;
+    // Name, symbol and decimals only, served from the metadata cache when present
+    async fn get_token_metadata(&self, token_address: &str, block: Option<BlockId>) -> Result<TokenMetadata, BlockchainServiceError>;
+    async fn get_account_balance(&self, token_address: &str, wallet_addresses:Vec<String>, include_share: bool, block: Option<BlockId>) -> Result<Vec<AccountBalance>, BlockchainServiceError>;
+    async fn get_native_balance(&self, wallet_addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<AccountBalance>, BlockchainServiceError>;
+    async fn get_transfers(&self, token_address: &str, query: TransferQuery) -> Result<TransferPage, BlockchainServiceError>;
+    async fn stream_transfers(&self, token_address: &str, addresses: Vec<String>, resume_after: Option<TransferCursor>) -> Result<BoxStream<'static, TokenTransfer>, BlockchainServiceError>;
+    async fn get_allowances(&self, token_address: &str, owner_address: &str, spender_addresses: Vec<String>) -> Result<Vec<Allowance>, BlockchainServiceError>;
+    // Gas and EIP-1559 fees of transferring a human-readable amount of the token, or why the transfer would revert
+    async fn estimate_transfer(&self, token_address: &str, from_address: &str, to_address: &str, amount: &str) -> Result<TransferEstimate, BlockchainServiceError>;
+    async fn get_nft_collection(&self, contract_address: &str, block: Option<BlockId>) -> Result<NftCollection, BlockchainServiceError>;
+    async fn get_nft_owners(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftOwner>, BlockchainServiceError>;
+    async fn get_nft_balances(&self, contract_address: &str, owner_addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftBalance>, BlockchainServiceError>;
+    async fn get_nft_token_uris(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftTokenUri>, BlockchainServiceError>;
+    async fn get_multitoken_balances(&self, contract_address: &str, wallet_addresses: Vec<String>, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<MultiTokenBalance>, BlockchainServiceError>;
+    async fn get_multitoken_uris(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<MultiTokenUri>, BlockchainServiceError>;
+    // Decode and check a signed transaction, then broadcast it with eth_sendRawTransaction
+    async fn send_raw_transaction(&self, raw_transaction: &str) -> Result<BroadcastTransaction, BlockchainServiceError>;
+    // Populate an unsigned EIP-1559 transaction for an external signer
+    async fn build_transaction(&self, request: TransactionBuildRequest) -> Result<UnsignedTransaction, BlockchainServiceError>;
+    // Receipt of a mined transaction with its decoded ERC20 logs, or the pending transaction
+    async fn get_transaction(&self, hash: H256) -> Result<TransactionDetails, BlockchainServiceError>;
+    // Reverse-resolved ENS primary name of each address, None when the address has no verified primary name
+    async fn lookup_names(&self, addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<Option<String>>, BlockchainServiceError>;
+    async fn get_contract_kind(&self, address: &str, block: Option<BlockId>) -> Result<ContractKind, BlockchainServiceError>;
+    // Encode a call from a runtime ABI, run it with eth_call and decode its outputs
+    async fn call_contract(&self, contract_address: &str, request: ContractCallRequest, block: Option<BlockId>) -> Result<ContractCallResult, BlockchainServiceError>;
+}
+
+// A configured chain as listed by the API
+#[derive(Debug, Serialize)]
+pub struct ChainSummary {
+    pub name: String,
+    pub chain_id: Option<u64>,
+    pub default: bool,
+}
+
+// Upstream health of a configured chain, the chain is served while any upstream is healthy
+#[derive(Debug, Serialize)]
+pub struct ChainHealth {
+    pub name: String,
+    pub healthy: bool,
+    pub upstreams: Vec<UpstreamStatus>,
 }
 
 // Facade to abstract blockchain interaction
 pub struct BlockchainService {
-    eth_client: EthereumClient,
-    // bsc_client: BinanceClient, // if we want to extend to other blockchains
+    // One client per configured EVM chain, keyed by chain name
+    chain_clients: HashMap<String, EthereumClient>,
+    // Chain ids of the configured chains, so routes can address a chain by id as well as by name
+    chain_ids: HashMap<u64, String>,
+    default_chain: String,
+    token_cache: Arc<TokenMetadataCache>,
 }
 
 impl BlockchainService {
-    pub fn new(app_config: &AppConfig) -> Self {
-        BlockchainService {
-            eth_client: EthereumClient::new(app_config.environment.provider_url.as_str()), // Adjust the provider URL as needed
-            // bsc_client: BinanceClient::new("https://bsc-dataseed.binance.org/"), // for Binance Smart Chain
+    pub fn new(app_config: &AppConfig) -> Result<Self, AppServerError> {
+        let token_cache = Arc::new(TokenMetadataCache::new(
+            Duration::from_secs(app_config.environment.token_cache_ttl_secs),
+            app_config.environment.token_cache_max_entries,
+        ));
+
+        let abi_registry = Arc::new(AbiRegistry::load(app_config.environment.abi_dir.as_deref())?);
+
+        let mut chain_clients = HashMap::new();
+        let mut chain_ids = HashMap::new();
+        for chain in app_config.environment.chains() {
+            if let Some(chain_id) = chain.chain_id {
+                chain_ids.insert(chain_id, chain.name.clone());
+            }
+            let client = EthereumClient::new(&chain, &app_config.environment, token_cache.clone(), abi_registry.clone())?;
+            chain_clients.insert(chain.name, client);
         }
+
+        Ok(BlockchainService {
+            chain_clients,
+            chain_ids,
+            default_chain: app_config.environment.default_chain(),
+            token_cache,
+        })
     }
 
-    // Determine the correct blockchain client based on some condition
-    pub fn get_blockchain_client(&self, blockchain: BlockchainType) -> &dyn BlockchainAdapter {
-        match blockchain {
-            BlockchainType::Ethereum => &self.eth_client,
+    // Resolve a chain by name or chain id, or the default chain when none is given
+    pub fn get_blockchain_client(&self, chain: Option<&str>) -> Result<&dyn BlockchainAdapter, BlockchainServiceError> {
+        let chain_name = self.chain_name(chain.unwrap_or(&self.default_chain))?;
+        Ok(&self.chain_clients[chain_name])
+    }
+
+    // Configured name of a chain given by name or chain id
+    pub fn chain_name(&self, chain: &str) -> Result<&str, BlockchainServiceError> {
+        let chain_name = match chain.parse::<u64>() {
+            Ok(chain_id) => self.chain_ids.get(&chain_id).map(String::as_str).unwrap_or(chain),
+            Err(_) => chain,
+        };
+
+        match self.chain_clients.get_key_value(chain_name) {
+            Some((chain_name, _)) => Ok(chain_name),
+            None => Err(BlockchainServiceError::UnknownChain(chain.to_string())),
         }
     }
-}
\ No newline at end of file
+
+    pub fn chains(&self) -> Vec<ChainSummary> {
+        let mut chains = self.chain_clients
+            .values()
+            .map(|client| ChainSummary {
+                name: client.chain().to_string(),
+                chain_id: client.chain_id(),
+                default: client.chain() == self.default_chain,
+            })
+            .collect::<Vec<_>>();
+        chains.sort_by(|a, b| a.name.cmp(&b.name));
+        chains
+    }
+
+    pub fn health(&self) -> Vec<ChainHealth> {
+        let mut chains = self.chain_clients
+            .values()
+            .map(|client| {
+                let upstreams = client.upstream_status();
+                ChainHealth {
+                    name: client.chain().to_string(),
+                    healthy: upstreams.iter().any(|upstream| upstream.healthy),
+                    upstreams,
+                }
+            })
+            .collect::<Vec<_>>();
+        chains.sort_by(|a, b| a.name.cmp(&b.name));
+        chains
+    }
+
+    pub fn token_cache(&self) -> &TokenMetadataCache {
+        &self.token_cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_address_or_ens_name() {
+        assert!(is_address_or_ens_name("0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe"));
+        assert!(is_address_or_ens_name("0xd87d2676b8bbd7d4bf7884089356f7bb82158cfe"));
+        assert!(is_address_or_ens_name("vitalik.eth"));
+        assert!(is_address_or_ens_name("pay.alice.eth"));
+
+        assert!(!is_address_or_ens_name("0x1234"));
+        assert!(!is_address_or_ens_name("eth"));
+        assert!(!is_address_or_ens_name("vitalik..eth"));
+        assert!(!is_address_or_ens_name(".eth"));
+        assert!(!is_address_or_ens_name("vitalik .eth"));
+        assert!(!is_address_or_ens_name(""));
+    }
+}
@@ -1,70 +1,2313 @@
-use ethers::providers::{Http, Provider};
-use ethers::types::{Address, U256};
+use ethers::providers::{ens, Middleware, Provider, ProviderError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, BigEndianHash, I256, BlockId, BlockNumber, Bytes, Eip1559TransactionRequest, Log, TransactionRequest, H256, U256, U512};
 use std::convert::TryFrom;
 use crate::prelude::*;
 use crate::domain::token::TokenInfo;
 use crate::domain::account::AccountBalance;
-use crate::error::BlockchainServiceError;
-use crate::services::blockchain_service::BlockchainAdapter;
-use ethers::contract::abigen;
-use ethers::utils::format_units;
+use crate::domain::allowance::Allowance;
+use crate::domain::nft::{NftBalance, NftCollection, NftOwner, NftTokenUri};
+use crate::domain::multitoken::{MultiTokenBalance, MultiTokenUri};
+use crate::domain::contract::{AbiSource, AddressKind, ContractCallOutput, ContractCallRequest, ContractCallResult, ContractKind, ProxyInfo, ProxyKind, TokenStandard};
+use crate::domain::fee::{FeeSuggestion, TransferEstimate};
+use crate::domain::transaction::{
+    AccessListEntry, BroadcastTransaction, Erc20Intent, Erc20Log, FeeSpeed, TransactionBuildRequest, TransactionDetails, TransactionIntent,
+    TransactionStatus, UnsignedTransaction,
+};
+use crate::domain::transfer::{TokenTransfer, TransferCursor, TransferPage, TransferQuery};
+use crate::services::abi_registry::{parse_abi_value, AbiRegistry};
+use crate::services::token_cache::{TokenMetadata, TokenMetadataCache};
+use crate::services::transfer_stream::TransferStream;
+use crate::services::upstream::{EthProvider, UpstreamPool, UpstreamStatus};
+use crate::environment::config::{ChainConfig, EnvConfig, TokenOverride};
+use crate::error::{AppServerError, BlockchainServiceError};
+use crate::services::blockchain_service::{is_address_or_ens_name, BlockchainAdapter};
+use ethers::contract::{abigen, ContractCall, ContractError, EthError, EthLogDecode, LogMeta, Multicall};
+use ethers::abi::{Abi, AbiDecode, AbiEncode, Detokenize, Function, ParamType, Token};
+use ethers::providers::RpcError;
+use ethers::utils::{format_units, hex, keccak256, parse_units, rlp, to_checksum};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 
 abigen!(
     IERC20,
     "./src/abi/erc20.json",
 );
 
+// Kept in its own module so its generated call types do not clash with the ERC20 ones
+mod erc721 {
+    ethers::contract::abigen!(
+        IERC721,
+        "./src/abi/erc721.json",
+    );
+}
+use erc721::IERC721;
+
+mod erc1155 {
+    ethers::contract::abigen!(
+        IERC1155,
+        "./src/abi/erc1155.json",
+    );
+}
+use erc1155::IERC1155;
+
+// ERC-165 interface ids
+const ERC165_INTERFACE_ID: [u8; 4] = [0x01, 0xff, 0xc9, 0xa7];
+const INVALID_INTERFACE_ID: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+const ERC721_INTERFACE_ID: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+// ERC-721 enumerable extension, which provides totalSupply
+const ERC721_ENUMERABLE_INTERFACE_ID: [u8; 4] = [0x78, 0x0e, 0x9d, 0x63];
+const ERC1155_INTERFACE_ID: [u8; 4] = [0xd9, 0xb6, 0x7a, 0x26];
+
+// Runtime code of an EIP-1167 minimal proxy around the 20 byte implementation address
+const EIP1167_CODE_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const EIP1167_CODE_SUFFIX: [u8; 15] = [0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3];
+
+// Selector of implementation() on an EIP-1967 beacon
+const BEACON_IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b];
+
+// Selector of Panic(uint256), raised by failed asserts and arithmetic errors
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+// Recent blocks whose priority fees back the fee suggestions, and the percentiles suggested
+const FEE_HISTORY_BLOCKS: u64 = 10;
+const PRIORITY_FEE_PERCENTILES: [f64; 3] = [25.0, 50.0, 75.0];
+
+// Native ether is denominated in wei
+const NATIVE_DECIMALS: u32 = 18;
+
+// Number of decimal places kept when expressing a holder share as a percentage of supply
+const SHARE_OF_SUPPLY_DECIMALS: u32 = 6;
+
 // Ethereum-specific implementation of the blockchain service
 pub struct EthereumClient {
-    provider: Arc<Provider<Http>>,
+    // Name of the chain, used to key shared caches
+    chain: String,
+    chain_id: Option<u64>,
+    provider: Arc<EthProvider>,
+    upstream_pool: UpstreamPool,
+    token_cache: Arc<TokenMetadataCache>,
+    abi_registry: Arc<AbiRegistry>,
+    token_overrides: HashMap<Address, TokenOverride>,
+    strict_address_checksum: bool,
+    ens_registry: Address,
+    multicall_address: Option<Address>,
+    multicall_batch_size: usize,
+    max_concurrent_calls: usize,
+    log_block_range: u64,
+    max_scan_blocks: u64,
+    max_multitoken_pairs: usize,
+    stream_poll_interval: Duration,
 }
 
 impl EthereumClient {
-    pub fn new(provider_url: &str) -> Self {
-        // Connect to the Ethereum node
-        let provider = Arc::new(Provider::<Http>::try_from(provider_url).expect("Failed to connect to Ethereum node"));
+    pub fn new(chain: &ChainConfig, env_config: &EnvConfig, token_cache: Arc<TokenMetadataCache>, abi_registry: Arc<AbiRegistry>) -> Result<Self, AppServerError> {
+        // Connect to the chain's nodes
+        let upstream_pool = UpstreamPool::new(chain, env_config)?;
+        let provider = Arc::new(Provider::new(upstream_pool.clone()));
+        let ens_registry = match chain.ens_registry_address.as_ref().or(env_config.ens_registry_address.as_ref()) {
+            Some(ens_registry_address) => ens_registry_address.parse::<Address>().map_err(|_| {
+                AppServerError::ConfigError(format!("Chain {} has an invalid ENS registry address", chain.name))
+            })?,
+            None => ens::ENS_ADDRESS,
+        };
+        let multicall_address = chain.multicall_address.as_ref()
+            .or(env_config.multicall_address.as_ref())
+            .map(|address| address.parse::<Address>().map_err(|_| {
+                AppServerError::ConfigError(format!("Chain {} has an invalid multicall address", chain.name))
+            }))
+            .transpose()?;
+        let token_overrides = chain.token_overrides
+            .iter()
+            .map(|token_override| match token_override.address.parse::<Address>() {
+                Ok(address) => Ok((address, token_override.clone())),
+                Err(_) => Err(AppServerError::ConfigError(format!("Chain {} has a token override with an invalid address {}", chain.name, token_override.address))),
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        Ok(EthereumClient {
+            chain: chain.name.clone(),
+            chain_id: chain.chain_id,
+            provider,
+            upstream_pool,
+            token_cache,
+            abi_registry,
+            token_overrides,
+            strict_address_checksum: env_config.strict_address_checksum,
+            ens_registry,
+            multicall_address,
+            multicall_batch_size: env_config.multicall_batch_size.max(1),
+            max_concurrent_calls: env_config.max_concurrent_calls.max(1),
+            log_block_range: env_config.log_block_range.max(1),
+            max_scan_blocks: env_config.max_scan_blocks.max(1),
+            max_multitoken_pairs: env_config.max_multitoken_pairs.max(1),
+            stream_poll_interval: Duration::from_millis(env_config.stream_poll_interval_ms.max(1)),
+        })
+    }
+
+    pub fn chain(&self) -> &str {
+        &self.chain
+    }
+
+    pub fn chain_id(&self) -> Option<u64> {
+        self.chain_id
+    }
+
+    pub fn upstream_status(&self) -> Vec<UpstreamStatus> {
+        self.upstream_pool.status()
+    }
+
+    // Resolve the multicall address and block to aggregate reads at, if aggregation is possible.
+    // Multicall can only pin reads to a block number or tag, so reads at a block hash go through individual calls.
+    fn multicall_target(&self, block: Option<BlockId>) -> Option<(Address, Option<BlockNumber>)> {
+        let multicall_address = self.multicall_address?;
+        match block {
+            None => Some((multicall_address, None)),
+            Some(BlockId::Number(block_number)) => Some((multicall_address, Some(block_number))),
+            Some(BlockId::Hash(_)) => None,
+        }
+    }
+
+    fn multicall(&self, (multicall_address, block_number): (Address, Option<BlockNumber>)) -> Result<Multicall<EthProvider>, BlockchainServiceError> {
+        let multicall = Multicall::new_with_chain_id(self.provider.clone(), Some(multicall_address), self.chain_id)
+            .map_err(BlockchainServiceError::EthMulticallError)?;
+
+        Ok(match block_number {
+            Some(block_number) => multicall.block(block_number),
+            None => multicall,
+        })
+    }
+
+    // Fetch name, symbol and decimals from the cache, or in a single aggregated eth_call when multicall is configured.
+    // Tokens that do not decode as the standard ABI are read again field by field, see fetch_token_metadata_raw.
+    // The cache only holds metadata read at the latest block, reads at any other block bypass it.
+    async fn fetch_token_metadata(&self, contract: &IERC20<EthProvider>, block: Option<BlockId>) -> Result<TokenMetadata, BlockchainServiceError> {
+        let cacheable = is_latest(block);
+        if cacheable {
+            if let Some(metadata) = self.token_cache.get(&self.chain, contract.address()) {
+                return Ok(metadata);
+            }
+        }
+
+        let token_override = self.token_overrides.get(&contract.address());
+        if let Some(token_override) = token_override {
+            // Nothing left to read from a fully configured token
+            let metadata = TokenMetadata::default().with_override(token_override);
+            if metadata.name.is_some() && metadata.symbol.is_some() && metadata.decimals.is_some() {
+                return Ok(metadata);
+            }
+        }
+
+        let metadata_call: Result<(String, String, u8), BlockchainServiceError> = async {
+            match self.multicall_target(block) {
+                Some(multicall_target) => {
+                    let mut multicall = self.multicall(multicall_target)?;
+                    multicall
+                        .add_call(contract.name(), false)
+                        .add_call(contract.symbol(), false)
+                        .add_call(contract.decimals(), false);
+                    Ok(multicall.call::<(String, String, u8)>().await?)
+                }
+                None => {
+                    let (name_call, symbol_call, decimals_call) = (
+                        at_block(contract.name(), block),
+                        at_block(contract.symbol(), block),
+                        at_block(contract.decimals(), block),
+                    );
+                    Ok(tokio::try_join!(name_call.call(), symbol_call.call(), decimals_call.call())?)
+                }
+            }
+        }.await;
+        let mut metadata = match metadata_call {
+            Ok((name, symbol, decimals)) => TokenMetadata { name: Some(name), symbol: Some(symbol), decimals: Some(decimals) },
+            Err(e) if e.is_not_erc20() || e.is_revert() => {
+                let metadata = self.fetch_token_metadata_raw(contract, block).await?;
+                if metadata.is_empty() && token_override.is_none() {
+                    return Err(self.explain_contract_error(contract.address(), block, e).await);
+                }
+                metadata
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(token_override) = token_override {
+            metadata = metadata.with_override(token_override);
+        }
+
+        if cacheable {
+            self.token_cache.insert(&self.chain, contract.address(), metadata.clone());
+        }
+        Ok(metadata)
+    }
+
+    // name, symbol and decimals are optional in ERC20 and some tokens return name and symbol as bytes32.
+    // Read each one on its own and decode it leniently, a getter that reverts or returns garbage is left absent.
+    async fn fetch_token_metadata_raw(&self, contract: &IERC20<EthProvider>, block: Option<BlockId>) -> Result<TokenMetadata, BlockchainServiceError> {
+        let (name_call, symbol_call, decimals_call) = (
+            at_block(contract.name(), block),
+            at_block(contract.symbol(), block),
+            at_block(contract.decimals(), block),
+        );
+        let (name, symbol, decimals) = tokio::try_join!(
+            optional_call(name_call.call_raw_bytes()),
+            optional_call(symbol_call.call_raw_bytes()),
+            optional_call(decimals_call.call_raw_bytes()),
+        )?;
+
+        Ok(TokenMetadata {
+            name: name.as_ref().and_then(decode_metadata_string),
+            symbol: symbol.as_ref().and_then(decode_metadata_string),
+            decimals: decimals.as_ref().and_then(decode_decimals),
+        })
+    }
+
+    // Parse a hex address, or resolve an ENS name through the chain's ENS registry
+    async fn resolve_address(&self, input: &str, kind: &str, block: Option<BlockId>) -> Result<Address, BlockchainServiceError> {
+        match input.parse::<Address>() {
+            Ok(address) if self.strict_address_checksum && !has_valid_checksum(input, &address) => {
+                return Err(BlockchainServiceError::InvalidAddress(format!("{} address [{}] has an invalid EIP-55 checksum", kind, input)));
+            }
+            Ok(address) => return Ok(address),
+            Err(e) if !is_address_or_ens_name(input) => {
+                return Err(BlockchainServiceError::InvalidAddress(format!("{} address [{}] {:?}", kind, input, e)));
+            }
+            Err(_) => {}
+        }
+
+        match self.resolve_ens_name(input, block).await? {
+            Some(address) => Ok(address),
+            None => Err(BlockchainServiceError::EnsNameNotFound(input.to_string())),
+        }
+    }
+
+    // ENS lookups are plain eth_calls decoded leniently, at the block the rest of the request reads. Anyone can
+    // point a name or a reverse record at an arbitrary resolver, so a missing, reverting or misbehaving resolver
+    // reads as no record.
+
+    // Address an ENS name resolves to, None without a resolver or address record
+    async fn resolve_ens_name(&self, name: &str, block: Option<BlockId>) -> Result<Option<Address>, BlockchainServiceError> {
+        let Some(resolver) = self.ens_resolver(name, block).await? else {
+            return Ok(None);
+        };
+        let addr_call = ens::resolve(resolver, ens::ADDR_SELECTOR, name, None).into();
+        let data = optional_call(self.provider.call(&addr_call, block)).await?;
+        Ok(data.as_ref().and_then(decode_address))
+    }
+
+    // Primary name of an address, only when the name resolves back to the address
+    async fn lookup_ens_name(&self, address: Address, block: Option<BlockId>) -> Result<Option<String>, BlockchainServiceError> {
+        let reverse_name = ens::reverse_address(address);
+        let Some(resolver) = self.ens_resolver(&reverse_name, block).await? else {
+            return Ok(None);
+        };
+        let name_call = ens::resolve(resolver, ens::NAME_SELECTOR, &reverse_name, None).into();
+        let data = optional_call(self.provider.call(&name_call, block)).await?;
+        let Some(name) = data.as_ref().and_then(decode_string) else {
+            return Ok(None);
+        };
+
+        match self.resolve_ens_name(&name, block).await? {
+            Some(resolved) if resolved == address => Ok(Some(name)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn ens_resolver(&self, name: &str, block: Option<BlockId>) -> Result<Option<Address>, BlockchainServiceError> {
+        let resolver_call = ens::get_resolver(self.ens_registry, name).into();
+        let data = optional_call(self.provider.call(&resolver_call, block)).await?;
+        Ok(data.as_ref().and_then(decode_address))
+    }
+
+    // Resolve several addresses or names, keeping their order.
+    // Inputs that end up at the same address, in another casing or as an ENS name, are only kept once.
+    async fn resolve_addresses(&self, inputs: &[String], kind: &str, block: Option<BlockId>) -> Result<Vec<Address>, BlockchainServiceError> {
+        let resolve_calls = inputs
+            .iter()
+            .map(|input| self.resolve_address(input, kind, block))
+            .collect::<Vec<_>>();
+        let addresses: Vec<Address> = stream::iter(resolve_calls)
+            .buffered(self.max_concurrent_calls)
+            .try_collect()
+            .await?;
+
+        let mut seen = HashSet::new();
+        Ok(addresses.into_iter().filter(|address| seen.insert(*address)).collect())
+    }
+
+    // Implementation behind an EIP-1167 minimal proxy or an EIP-1967 (beacon) proxy, if the contract is one
+    async fn fetch_proxy(&self, address: Address, code: &Bytes, block: Option<BlockId>) -> Result<Option<ProxyInfo>, BlockchainServiceError> {
+        if let Some(implementation) = eip1167_implementation(code) {
+            return Ok(Some(ProxyInfo { kind: ProxyKind::Eip1167, implementation: to_checksum(&implementation, None) }));
+        }
+
+        let (implementation_slot, beacon_slot) = tokio::try_join!(
+            self.provider.get_storage_at(address, eip1967_slot("eip1967.proxy.implementation"), block),
+            self.provider.get_storage_at(address, eip1967_slot("eip1967.proxy.beacon"), block),
+        )?;
+        if let Some(implementation) = slot_address(implementation_slot) {
+            return Ok(Some(ProxyInfo { kind: ProxyKind::Eip1967, implementation: to_checksum(&implementation, None) }));
+        }
+        let Some(beacon) = slot_address(beacon_slot) else {
+            return Ok(None);
+        };
+
+        let implementation_call = TransactionRequest::new().to(beacon).data(BEACON_IMPLEMENTATION_SELECTOR.to_vec()).into();
+        let implementation = optional_call(self.provider.call(&implementation_call, block)).await?;
+        Ok(implementation.as_ref().and_then(decode_address).map(|implementation| {
+            ProxyInfo { kind: ProxyKind::Eip1967Beacon, implementation: to_checksum(&implementation, None) }
+        }))
+    }
+
+    // ERC-165 compliance as specified: supportsInterface answers true for its own id and false for 0xffffffff
+    async fn fetch_interface_standards(&self, address: Address, block: Option<BlockId>) -> Result<Vec<TokenStandard>, BlockchainServiceError> {
+        let contract = IERC721::new(address, self.provider.clone());
+        let supports_interface = |interface_id| {
+            let call = at_block(contract.supports_interface(interface_id), block);
+            async move { Ok::<_, BlockchainServiceError>(optional_call(call.call_raw_bytes()).await?.as_ref().and_then(decode_bool) == Some(true)) }
+        };
+
+        let (erc165, invalid) = tokio::try_join!(supports_interface(ERC165_INTERFACE_ID), supports_interface(INVALID_INTERFACE_ID))?;
+        if !erc165 || invalid {
+            return Ok(Vec::new());
+        }
+
+        let (erc721, erc1155) = tokio::try_join!(supports_interface(ERC721_INTERFACE_ID), supports_interface(ERC1155_INTERFACE_ID))?;
+        let mut standards = vec![TokenStandard::Erc165];
+        if erc721 {
+            standards.push(TokenStandard::Erc721);
+        }
+        if erc1155 {
+            standards.push(TokenStandard::Erc1155);
+        }
+        Ok(standards)
+    }
+
+    // ERC20 has no ERC-165 id, so call decimals, totalSupply and balanceOf and check that each answers with a uint.
+    // A contract with a catch-all fallback could still pass, hence only a heuristic.
+    async fn probe_erc20(&self, address: Address, block: Option<BlockId>) -> Result<bool, BlockchainServiceError> {
+        let contract = IERC20::new(address, self.provider.clone());
+        let (decimals_call, total_supply_call, balance_call) = (
+            at_block(contract.decimals(), block),
+            at_block(contract.total_supply(), block),
+            at_block(contract.balance_of(address), block),
+        );
+        let (decimals, total_supply, balance) = tokio::try_join!(
+            optional_call(decimals_call.call_raw_bytes()),
+            optional_call(total_supply_call.call_raw_bytes()),
+            optional_call(balance_call.call_raw_bytes()),
+        )?;
+
+        let is_uint = |data: &Option<Bytes>| data.as_ref().is_some_and(|data| data.len() == 32);
+        Ok(decimals.as_ref().and_then(decode_decimals).is_some() && is_uint(&total_supply) && is_uint(&balance))
+    }
+
+    // Chain id transactions must be signed for, read from the node when the chain config does not set one
+    async fn expected_chain_id(&self) -> Result<u64, BlockchainServiceError> {
+        match self.chain_id {
+            Some(chain_id) => Ok(chain_id),
+            None => Ok(self.provider.get_chainid().await?.as_u64()),
+        }
+    }
+
+    // ERC20 transfer or approve encoded in calldata sent to `token`.
+    // The calldata alone only names a function, reading the token metadata confirms the target is an ERC20.
+    async fn decode_erc20_intent(&self, token: Address, data: Option<&Bytes>) -> Result<Option<Erc20Intent>, BlockchainServiceError> {
+        let call = match data.map(IERC20Calls::decode) {
+            Some(Ok(call @ (IERC20Calls::Transfer(_) | IERC20Calls::Approve(_)))) => call,
+            _ => return Ok(None),
+        };
+
+        let contract = IERC20::new(token, self.provider.clone());
+        let metadata = match self.fetch_token_metadata(&contract, None).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.is_not_erc20() || e.is_not_a_contract() || e.is_revert() => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let token = to_checksum(&token, None);
+        let decimals = u32::from(metadata.unit_decimals());
+        Ok(match call {
+            IERC20Calls::Transfer(TransferCall { recipient, amount }) => Some(Erc20Intent::Transfer {
+                token,
+                symbol: metadata.symbol,
+                recipient: to_checksum(&recipient, None),
+                amount: format_units(amount, decimals)?,
+                amount_raw: amount.to_string(),
+            }),
+            IERC20Calls::Approve(ApproveCall { spender, amount }) => Some(Erc20Intent::Approve {
+                token,
+                symbol: metadata.symbol,
+                spender: to_checksum(&spender, None),
+                amount: format_units(amount, decimals)?,
+                amount_raw: amount.to_string(),
+            }),
+            _ => None,
+        })
+    }
+
+    // Decode the ERC20 Transfer and Approval events among receipt logs.
+    // ERC-721 Transfer shares the event signature but indexes the token id, so it does not decode and is skipped.
+    async fn decode_erc20_logs(&self, logs: &[Log]) -> Result<Vec<Erc20Log>, BlockchainServiceError> {
+        let events = logs
+            .iter()
+            .filter_map(|log| IERC20Events::decode_log(&log.clone().into()).ok().map(|event| (log, event)))
+            .collect::<Vec<_>>();
+
+        // Every token's metadata is read once, however many of its events the receipt holds
+        let tokens = events.iter().map(|(log, _)| log.address).collect::<HashSet<_>>();
+        let metadata_calls = tokens
+            .into_iter()
+            .map(|token| async move {
+                let contract = IERC20::new(token, self.provider.clone());
+                match self.fetch_token_metadata(&contract, None).await {
+                    Ok(metadata) => Ok((token, metadata)),
+                    // Formatted in raw units when the emitter does not expose ERC20 metadata
+                    Err(e) if e.is_not_erc20() || e.is_not_a_contract() || e.is_revert() => Ok((token, TokenMetadata::default())),
+                    Err(e) => Err(e),
+                }
+            })
+            .collect::<Vec<_>>();
+        let metadata: HashMap<Address, TokenMetadata> = stream::iter(metadata_calls)
+            .buffered(self.max_concurrent_calls)
+            .try_collect()
+            .await?;
+
+        let mut erc20_logs = Vec::with_capacity(events.len());
+        for (log, event) in events {
+            let token_metadata = &metadata[&log.address];
+            let decimals = u32::from(token_metadata.unit_decimals());
+            let (token, symbol, log_index) = (to_checksum(&log.address, None), token_metadata.symbol.clone(), log.log_index.unwrap_or_default().as_u64());
+            erc20_logs.push(match event {
+                IERC20Events::TransferFilter(TransferFilter { from, to, value }) => Erc20Log::Transfer {
+                    token,
+                    symbol,
+                    log_index,
+                    from: to_checksum(&from, None),
+                    to: to_checksum(&to, None),
+                    amount: format_units(value, decimals)?,
+                    amount_raw: value.to_string(),
+                },
+                IERC20Events::ApprovalFilter(ApprovalFilter { owner, spender, value }) => Erc20Log::Approval {
+                    token,
+                    symbol,
+                    log_index,
+                    owner: to_checksum(&owner, None),
+                    spender: to_checksum(&spender, None),
+                    amount: format_units(value, decimals)?,
+                    amount_raw: value.to_string(),
+                },
+            });
+        }
+        Ok(erc20_logs)
+    }
+
+    // Base fee of the next block and the priority fee at each of PRIORITY_FEE_PERCENTILES over recent blocks
+    async fn fee_history_summary(&self) -> Result<(U256, Vec<U256>), BlockchainServiceError> {
+        let fee_history = self
+            .provider
+            .fee_history(FEE_HISTORY_BLOCKS, BlockNumber::Latest, &PRIORITY_FEE_PERCENTILES)
+            .await?;
+        // The last base fee is the one of the block after the requested range
+        let base_fee = fee_history.base_fee_per_gas.last().copied().unwrap_or_default();
+        Ok((base_fee, median_priority_fees(&fee_history.reward)))
+    }
+
+    // Recipient, value in wei and calldata carrying out an intent
+    async fn intent_call(&self, intent: TransactionIntent) -> Result<(Address, U256, Bytes), BlockchainServiceError> {
+        match intent {
+            TransactionIntent::NativeTransfer { to, amount } => {
+                let to = self.resolve_address(to.trim(), "recipient", None).await?;
+                Ok((to, parse_token_amount(&amount, NATIVE_DECIMALS as u8)?, Bytes::default()))
+            }
+            TransactionIntent::Erc20Transfer { token, to, amount } => {
+                let (token, recipient) = tokio::try_join!(
+                    self.resolve_address(token.trim(), "token", None),
+                    self.resolve_address(to.trim(), "recipient", None),
+                )?;
+                let amount = self.parse_erc20_amount(token, &amount).await?;
+                Ok((token, U256::zero(), IERC20Calls::Transfer(TransferCall { recipient, amount }).encode().into()))
+            }
+            TransactionIntent::Erc20Approve { token, spender, amount } => {
+                let (token, spender) = tokio::try_join!(
+                    self.resolve_address(token.trim(), "token", None),
+                    self.resolve_address(spender.trim(), "spender", None),
+                )?;
+                let amount = self.parse_erc20_amount(token, &amount).await?;
+                Ok((token, U256::zero(), IERC20Calls::Approve(ApproveCall { spender, amount }).encode().into()))
+            }
+            TransactionIntent::ContractCall { to, data, value } => {
+                let to = self.resolve_address(to.trim(), "contract", None).await?;
+                let data = data.trim().parse::<Bytes>().map_err(|e| {
+                    BlockchainServiceError::InvalidTransaction(format!("calldata is not hex encoded: {}", e))
+                })?;
+                let value = match value {
+                    Some(value) => parse_token_amount(&value, NATIVE_DECIMALS as u8)?,
+                    None => U256::zero(),
+                };
+                Ok((to, value, data))
+            }
+        }
+    }
+
+    // Human-readable amount of the token in its smallest unit
+    async fn parse_erc20_amount(&self, token: Address, amount: &str) -> Result<U256, BlockchainServiceError> {
+        let contract = IERC20::new(token, self.provider.clone());
+        let metadata = self.fetch_token_metadata(&contract, None).await?;
+        parse_token_amount(amount, metadata.unit_decimals())
+    }
+
+    // Calls to an address without code return empty data, which reads as a decoding failure.
+    // Check the code so callers can tell a missing contract apart from a contract that is not an ERC20.
+    async fn explain_contract_error(&self, address: Address, block: Option<BlockId>, e: BlockchainServiceError) -> BlockchainServiceError {
+        if !(e.is_not_erc20() || e.is_revert()) {
+            return e;
+        }
+
+        match self.provider.get_code(address, block).await {
+            Ok(code) if code.is_empty() => BlockchainServiceError::NotAContract(to_checksum(&address, None)),
+            _ => e,
+        }
+    }
+
+    // Total supply changes with every mint and burn, so it is always read from the chain
+    async fn fetch_total_supply(&self, contract: &IERC20<EthProvider>, block: Option<BlockId>) -> Result<U256, BlockchainServiceError> {
+        Ok(at_block(contract.total_supply(), block).call().await?)
+    }
+
+    // Run uint256 view calls in batches of aggregated eth_calls, or one eth_call per call without multicall.
+    // Either way at most `max_concurrent_calls` requests are in flight and the input order is preserved.
+    async fn call_u256_batch(&self, calls: &[ContractCall<EthProvider, U256>], block: Option<BlockId>) -> Result<Vec<U256>, BlockchainServiceError> {
+        match self.multicall_target(block) {
+            Some(multicall_target) => {
+                let batch_calls = calls
+                    .chunks(self.multicall_batch_size)
+                    .map(|batch| {
+                        let multicall = self.multicall(multicall_target).map(|mut multicall| {
+                            multicall.add_calls(false, batch.iter().cloned());
+                            multicall
+                        });
+                        async move { multicall?.call_array::<U256>().await.map_err(BlockchainServiceError::EthMulticallError) }
+                    })
+                    .collect::<Vec<_>>();
+                let batches: Vec<Vec<U256>> = stream::iter(batch_calls)
+                    .buffered(self.max_concurrent_calls)
+                    .try_collect()
+                    .await?;
+
+                Ok(batches.into_iter().flatten().collect())
+            }
+            None => {
+                let single_calls = calls
+                    .iter()
+                    .map(|call| {
+                        let call = at_block(call.clone(), block);
+                        async move { call.call().await.map_err(BlockchainServiceError::EthContractError) }
+                    })
+                    .collect::<Vec<_>>();
+                stream::iter(single_calls)
+                    .buffered(self.max_concurrent_calls)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+
+    // Like call_u256_batch for calls that may revert, e.g. ownerOf of a token that does not exist.
+    // A reverted call yields None instead of failing the whole batch.
+    async fn call_optional_batch<D: Detokenize + Send + Sync + 'static>(&self, calls: &[ContractCall<EthProvider, D>], block: Option<BlockId>) -> Result<Vec<Option<D>>, BlockchainServiceError> {
+        match self.multicall_target(block) {
+            Some(multicall_target) => {
+                let batch_calls = calls
+                    .chunks(self.multicall_batch_size)
+                    .map(|batch| {
+                        let multicall = self.multicall(multicall_target).map(|mut multicall| {
+                            multicall.add_calls(true, batch.iter().cloned());
+                            multicall
+                        });
+                        async move { multicall?.call_raw().await.map_err(BlockchainServiceError::EthMulticallError) }
+                    })
+                    .collect::<Vec<_>>();
+                let batches: Vec<Vec<Result<Token, Bytes>>> = stream::iter(batch_calls)
+                    .buffered(self.max_concurrent_calls)
+                    .try_collect()
+                    .await?;
+
+                batches
+                    .into_iter()
+                    .flatten()
+                    .map(|result| match result {
+                        Ok(token) => D::from_tokens(vec![token]).map(Some).map_err(|e| BlockchainServiceError::EthContractError(ContractError::DetokenizationError(e))),
+                        Err(_) => Ok(None),
+                    })
+                    .collect()
+            }
+            None => {
+                let single_calls = calls
+                    .iter()
+                    .map(|call| {
+                        let call = at_block(call.clone(), block);
+                        async move {
+                            match call.call().await {
+                                Ok(value) => Ok(Some(value)),
+                                Err(e) => {
+                                    let e = BlockchainServiceError::EthContractError(e);
+                                    if e.is_revert() {
+                                        Ok(None)
+                                    } else {
+                                        Err(e)
+                                    }
+                                }
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                stream::iter(single_calls)
+                    .buffered(self.max_concurrent_calls)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
 
-        EthereumClient { provider }
+    // Fetch native balances with bounded parallel eth_getBalance. Multicall3 getEthBalance is not used, it fails
+    // at blocks before the contract was deployed and on chains without it.
+    async fn fetch_native_balances(&self, wallet_addresses: &[Address], block: Option<BlockId>) -> Result<Vec<U256>, BlockchainServiceError> {
+        let balance_calls = wallet_addresses
+            .iter()
+            .map(|address| async move { self.provider.get_balance(*address, block).await.map_err(BlockchainServiceError::EthProviderError) })
+            .collect::<Vec<_>>();
+        stream::iter(balance_calls)
+            .buffered(self.max_concurrent_calls)
+            .try_collect()
+            .await
     }
 }
 
 // Implement the common interface for Ethereum
 #[async_trait]
 impl BlockchainAdapter for EthereumClient {
-    async fn get_token_by_address(&self, token_address: &str) -> Result<TokenInfo, BlockchainServiceError> {
-        let parsed_token_address: Address = token_address.parse() .map_err(|e| {
-            BlockchainServiceError::InvalidAddress(format!("{:?}", e))
-        })?;
+    async fn get_token_by_address(&self, token_address: &str, block: Option<BlockId>) -> Result<TokenInfo, BlockchainServiceError> {
+        let parsed_token_address = self.resolve_address(token_address, "token", block).await?;
         // Create a contract instance
-        let contract: IERC20<Provider<Http>> = IERC20::new(parsed_token_address, self.provider.clone());
-        let symbol = contract.symbol().call().await.map_err(|e| { BlockchainServiceError::EthContractError(e) })?;
-        // Call the functions to get name, symbol, decimals
-        let decimals = contract.decimals().call().await.map_err(|e| { BlockchainServiceError::EthContractError(e) })?;
-        let name = contract.name().call().await.map_err(|e| { BlockchainServiceError::EthContractError(e) })?;
+        let contract: IERC20<EthProvider> = IERC20::new(parsed_token_address, self.provider.clone());
 
-        Ok(TokenInfo::new(name, symbol, decimals.into()))
+        let (metadata, total_supply) = tokio::try_join!(
+            self.fetch_token_metadata(&contract, block),
+            self.fetch_total_supply(&contract, block),
+        )?;
+
+        let decimals = metadata.unit_decimals();
+        Ok(TokenInfo::new(
+            metadata.name,
+            metadata.symbol,
+            metadata.decimals.map(i32::from),
+            format_units(total_supply, u32::from(decimals))?,
+            total_supply.to_string(),
+        ))
     }
 
-    async fn get_account_balance(&self, token_address: &str, wallet_addresses: Vec<String>) -> Result<Vec<AccountBalance>, BlockchainServiceError> {
-        let token_result = self.get_token_by_address(token_address).await.map_err(|e| { e })?;
-        let token_address: Address = token_address.parse().map_err(|e| {
-            BlockchainServiceError::InvalidAddress(format!("{:?}", e))
-        })?;
+    async fn get_token_metadata(&self, token_address: &str, block: Option<BlockId>) -> Result<TokenMetadata, BlockchainServiceError> {
+        let token_address = self.resolve_address(token_address, "token", block).await?;
+        let contract = IERC20::new(token_address, self.provider.clone());
+        self.fetch_token_metadata(&contract, block).await
+    }
+
+    async fn get_account_balance(&self, token_address: &str, wallet_addresses: Vec<String>, include_share: bool, block: Option<BlockId>) -> Result<Vec<AccountBalance>, BlockchainServiceError> {
+        let token_address = self.resolve_address(token_address, "token", block).await?;
+        let parsed_wallet_addresses = self.resolve_addresses(&wallet_addresses, "wallet", block).await?;
+        let wallet_addresses = checksum_addresses(&parsed_wallet_addresses);
 
         // Create a contract instance
         let contract = IERC20::new(token_address, self.provider.clone());
+        let balance_calls = parsed_wallet_addresses
+            .iter()
+            .map(|address| contract.balance_of(*address))
+            .collect::<Vec<_>>();
+
+        // Token metadata, balances and supply are independent, so fetch them concurrently
+        let total_supply_call = async {
+            if include_share {
+                self.fetch_total_supply(&contract, block).await.map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let (token_result, balances, total_supply) = tokio::try_join!(
+            self.fetch_token_metadata(&contract, block),
+            self.call_u256_batch(&balance_calls, block),
+            total_supply_call,
+        )?;
+
+        let mut account_balances = Vec::with_capacity(balances.len());
+        for (address_str, balance) in wallet_addresses.into_iter().zip(balances) {
+            let balance_str = format_units(balance, u32::from(token_result.unit_decimals())).map_err(|e| { BlockchainServiceError::EthConversionError(e) })?;
+            let share_of_supply = match total_supply {
+                Some(total_supply) => format_share_of_supply(balance, total_supply)?,
+                None => None,
+            };
+
+            account_balances.push(AccountBalance::new(address_str, balance_str, balance.to_string()).with_share_of_supply(share_of_supply));
+        }
+
+        Ok(account_balances)
+    }
+
+    async fn get_native_balance(&self, wallet_addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<AccountBalance>, BlockchainServiceError> {
+        let parsed_wallet_addresses = self.resolve_addresses(&wallet_addresses, "wallet", block).await?;
+        let wallet_addresses = checksum_addresses(&parsed_wallet_addresses);
+        let balances = self.fetch_native_balances(&parsed_wallet_addresses, block).await?;
 
-        let mut account_balances = Vec::new();
-        for address_str in wallet_addresses {
-            let address: Address = address_str.parse().map_err(|e| {
-                BlockchainServiceError::InvalidAddress(format!("wallet address [{}] {:?}", address_str, e))
-            })?;
-            let balance: U256 = contract.balance_of(address).call().await.map_err(|e| {BlockchainServiceError::EthContractError(e) })?;
-            let balance_str = format_units(balance, token_result.decimals).map_err(|e| { BlockchainServiceError::EthConversionError(e) })?;
+        let mut account_balances = Vec::with_capacity(balances.len());
+        for (address_str, balance) in wallet_addresses.into_iter().zip(balances) {
+            let balance_str = format_units(balance, NATIVE_DECIMALS)?;
 
-            account_balances.push(AccountBalance::new(address_str.to_string(), balance_str));
+            account_balances.push(AccountBalance::new(address_str, balance_str, balance.to_string()));
         }
 
         Ok(account_balances)
     }
-}
\ No newline at end of file
+
+    async fn get_transfers(&self, token_address: &str, query: TransferQuery) -> Result<TransferPage, BlockchainServiceError> {
+        let token_address = self.resolve_address(token_address, "token", None).await?;
+        let address = match query.address.as_deref() {
+            Some(address_str) => Some(self.resolve_address(address_str, "filter", None).await?),
+            None => None,
+        };
+
+        let contract = IERC20::new(token_address, self.provider.clone());
+        let (metadata, latest_block) = tokio::try_join!(
+            self.fetch_token_metadata(&contract, None),
+            async { self.provider.get_block_number().await.map_err(BlockchainServiceError::EthProviderError) },
+        )?;
+        let decimals = metadata.unit_decimals();
+
+        let to_block = query.to_block.unwrap_or(latest_block.as_u64()).min(latest_block.as_u64());
+        let mut from_block = query.from_block.unwrap_or(0);
+        if let Some(cursor) = query.cursor {
+            from_block = from_block.max(cursor.block_number);
+        }
+
+        // Walk the range in bounded eth_getLogs windows until the page is full, the range is exhausted
+        // or `max_scan_blocks` were scanned
+        let mut transfers = Vec::with_capacity(query.limit);
+        let mut scanned_blocks = 0;
+        while from_block <= to_block {
+            let window_end = from_block.saturating_add(self.log_block_range - 1).min(to_block);
+            let logs = fetch_transfer_logs(&contract, address.as_slice(), from_block, window_end).await?;
+
+            for (transfer, meta) in logs {
+                let transfer = to_token_transfer(transfer, meta, decimals)?;
+                if query.cursor.is_some_and(|cursor| transfer.cursor() <= cursor) {
+                    continue;
+                }
+
+                transfers.push(transfer);
+                if transfers.len() == query.limit {
+                    let next_cursor = transfers.last().map(TokenTransfer::cursor);
+                    return Ok(TransferPage::new(transfers, next_cursor));
+                }
+            }
+
+            scanned_blocks += window_end - from_block + 1;
+            from_block = window_end + 1;
+            if from_block <= to_block && scanned_blocks >= self.max_scan_blocks {
+                // Resume after every log of the last scanned block, even when the page is not full
+                return Ok(TransferPage::new(transfers, Some(TransferCursor::new(window_end, u64::MAX))));
+            }
+        }
+
+        Ok(TransferPage::new(transfers, None))
+    }
+
+    async fn stream_transfers(&self, token_address: &str, addresses: Vec<String>, resume_after: Option<TransferCursor>) -> Result<BoxStream<'static, TokenTransfer>, BlockchainServiceError> {
+        let token_address = self.resolve_address(token_address, "token", None).await?;
+        let parsed_addresses = self.resolve_addresses(&addresses, "filter", None).await?;
+
+        let contract = IERC20::new(token_address, self.provider.clone());
+        let decimals = self.fetch_token_metadata(&contract, None).await?.unit_decimals();
+        // Resume right after the given position, otherwise only stream transfers from blocks mined from now on
+        let next_block = match resume_after {
+            Some(cursor) => cursor.block_number,
+            None => self.provider.get_block_number().await?.as_u64() + 1,
+        };
+
+        let new_heads = self.upstream_pool.subscribe_new_heads().await;
+        let transfer_stream = TransferStream::subscribe(contract, parsed_addresses, decimals, next_block, resume_after, new_heads, self.stream_poll_interval, self.log_block_range).await?;
+        Ok(transfer_stream.into_stream())
+    }
+
+    async fn get_allowances(&self, token_address: &str, owner_address: &str, spender_addresses: Vec<String>) -> Result<Vec<Allowance>, BlockchainServiceError> {
+        let token_address = self.resolve_address(token_address, "token", None).await?;
+        let owner = self.resolve_address(owner_address, "owner", None).await?;
+        let owner_address = to_checksum(&owner, None);
+        let parsed_spender_addresses = self.resolve_addresses(&spender_addresses, "spender", None).await?;
+        let spender_addresses = checksum_addresses(&parsed_spender_addresses);
+
+        let contract = IERC20::new(token_address, self.provider.clone());
+        let allowance_calls = parsed_spender_addresses
+            .iter()
+            .map(|spender| contract.allowance(owner, *spender))
+            .collect::<Vec<_>>();
+
+        let (token_result, allowances) = tokio::try_join!(
+            self.fetch_token_metadata(&contract, None),
+            self.call_u256_batch(&allowance_calls, None),
+        )?;
+
+        let mut spender_allowances = Vec::with_capacity(allowances.len());
+        for (spender_str, allowance) in spender_addresses.into_iter().zip(allowances) {
+            let allowance_str = format_units(allowance, u32::from(token_result.unit_decimals()))?;
+
+            spender_allowances.push(Allowance::new(owner_address.clone(), spender_str, allowance_str, allowance.to_string()));
+        }
+
+        Ok(spender_allowances)
+    }
+
+    async fn estimate_transfer(&self, token_address: &str, from_address: &str, to_address: &str, amount: &str) -> Result<TransferEstimate, BlockchainServiceError> {
+        let (token_address, from, to) = tokio::try_join!(
+            self.resolve_address(token_address, "token", None),
+            self.resolve_address(from_address, "sender", None),
+            self.resolve_address(to_address, "recipient", None),
+        )?;
+        let contract = IERC20::new(token_address, self.provider.clone());
+
+        let (metadata, (base_fee, priority_fees)) = tokio::try_join!(
+            self.fetch_token_metadata(&contract, None),
+            self.fee_history_summary(),
+        )?;
+        let decimals = metadata.unit_decimals();
+        let amount_raw = parse_token_amount(amount, decimals)?;
+
+        // A transfer that would revert makes eth_estimateGas fail with the revert data
+        let (gas_limit, revert_reason) = match contract.transfer(to, amount_raw).from(from).estimate_gas().await {
+            Ok(gas_limit) => (Some(gas_limit), None),
+            Err(e) => match revert_reason(&e) {
+                Some(reason) => (None, Some(reason)),
+                None => return Err(e.into()),
+            },
+        };
+
+        let mut fees = Vec::with_capacity(priority_fees.len());
+        for (percentile, priority_fee) in PRIORITY_FEE_PERCENTILES.into_iter().zip(priority_fees) {
+            let max_fee = max_fee_per_gas(base_fee, priority_fee)?;
+            let (estimated_cost, max_cost) = match gas_limit {
+                Some(gas_limit) => (
+                    // Cannot overflow where the max fee did not
+                    Some(format_units(gas_cost(gas_limit, base_fee + priority_fee)?, NATIVE_DECIMALS)?),
+                    Some(format_units(gas_cost(gas_limit, max_fee)?, NATIVE_DECIMALS)?),
+                ),
+                None => (None, None),
+            };
+            fees.push(FeeSuggestion {
+                percentile,
+                max_priority_fee_per_gas: priority_fee.to_string(),
+                max_fee_per_gas: max_fee.to_string(),
+                estimated_cost,
+                max_cost,
+            });
+        }
+
+        Ok(TransferEstimate {
+            token: to_checksum(&token_address, None),
+            symbol: metadata.symbol,
+            from: to_checksum(&from, None),
+            to: to_checksum(&to, None),
+            amount: format_units(amount_raw, u32::from(decimals))?,
+            amount_raw: amount_raw.to_string(),
+            will_revert: revert_reason.is_some(),
+            revert_reason,
+            gas_limit: gas_limit.map(|gas_limit| gas_limit.to_string()),
+            base_fee_per_gas: base_fee.to_string(),
+            fees,
+        })
+    }
+
+    async fn get_nft_collection(&self, contract_address: &str, block: Option<BlockId>) -> Result<NftCollection, BlockchainServiceError> {
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+
+        let contract = IERC721::new(contract_address, self.provider.clone());
+        let (name_call, symbol_call, enumerable_call) = (
+            at_block(contract.name(), block),
+            at_block(contract.symbol(), block),
+            at_block(contract.supports_interface(ERC721_ENUMERABLE_INTERFACE_ID), block),
+        );
+        let (name, symbol, enumerable) = tokio::try_join!(
+            optional_call(name_call.call_raw_bytes()),
+            optional_call(symbol_call.call_raw_bytes()),
+            optional_call(enumerable_call.call_raw_bytes()),
+        )?;
+
+        let total_supply = match enumerable.as_ref().and_then(decode_bool) {
+            Some(true) => Some(at_block(contract.total_supply(), block).call().await?.to_string()),
+            _ => None,
+        };
+        let (name, symbol) = (name.as_ref().and_then(decode_metadata_string), symbol.as_ref().and_then(decode_metadata_string));
+        if name.is_none() && symbol.is_none() && total_supply.is_none() && self.provider.get_code(contract_address, block).await?.is_empty() {
+            return Err(BlockchainServiceError::NotAContract(to_checksum(&contract_address, None)));
+        }
+
+        Ok(NftCollection::new(name, symbol, total_supply))
+    }
+
+    async fn get_nft_owners(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftOwner>, BlockchainServiceError> {
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+        let parsed_token_ids = parse_token_ids(&token_ids)?;
+
+        let contract = IERC721::new(contract_address, self.provider.clone());
+        let owner_calls = parsed_token_ids
+            .iter()
+            .map(|token_id| contract.owner_of(*token_id))
+            .collect::<Vec<_>>();
+        let owners = self.call_optional_batch(&owner_calls, block).await?;
+
+        Ok(parsed_token_ids
+            .into_iter()
+            .zip(owners)
+            .map(|(token_id, owner)| NftOwner::new(token_id.to_string(), owner.map(|owner| to_checksum(&owner, None))))
+            .collect())
+    }
+
+    async fn get_nft_balances(&self, contract_address: &str, owner_addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftBalance>, BlockchainServiceError> {
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+        let parsed_owner_addresses = self.resolve_addresses(&owner_addresses, "owner", block).await?;
+        let owner_addresses = checksum_addresses(&parsed_owner_addresses);
+
+        let contract = IERC721::new(contract_address, self.provider.clone());
+        let balance_calls = parsed_owner_addresses
+            .iter()
+            .map(|owner| contract.balance_of(*owner))
+            .collect::<Vec<_>>();
+        let balances = match self.call_u256_batch(&balance_calls, block).await {
+            Ok(balances) => balances,
+            Err(e) => return Err(self.explain_contract_error(contract_address, block, e).await),
+        };
+
+        Ok(owner_addresses
+            .into_iter()
+            .zip(balances)
+            .map(|(address, balance)| NftBalance::new(address, balance.to_string()))
+            .collect())
+    }
+
+    async fn get_nft_token_uris(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<NftTokenUri>, BlockchainServiceError> {
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+        let parsed_token_ids = parse_token_ids(&token_ids)?;
+
+        let contract = IERC721::new(contract_address, self.provider.clone());
+        let token_uri_calls = parsed_token_ids
+            .iter()
+            .map(|token_id| contract.token_uri(*token_id))
+            .collect::<Vec<_>>();
+        let token_uris = self.call_optional_batch(&token_uri_calls, block).await?;
+
+        Ok(parsed_token_ids
+            .into_iter()
+            .zip(token_uris)
+            .map(|(token_id, token_uri)| NftTokenUri::new(token_id.to_string(), token_uri))
+            .collect())
+    }
+
+    async fn get_contract_kind(&self, address: &str, block: Option<BlockId>) -> Result<ContractKind, BlockchainServiceError> {
+        let parsed_address = self.resolve_address(address, "address", block).await?;
+
+        let code = self.provider.get_code(parsed_address, block).await?;
+        if code.is_empty() {
+            return Ok(ContractKind::new(to_checksum(&parsed_address, None), AddressKind::Eoa, Vec::new(), None));
+        }
+
+        // Calls to a proxy run the implementation code, so the probes see the implementation's standards
+        let (proxy, mut standards, erc20) = tokio::try_join!(
+            self.fetch_proxy(parsed_address, &code, block),
+            self.fetch_interface_standards(parsed_address, block),
+            self.probe_erc20(parsed_address, block),
+        )?;
+        // ERC-721 shares balanceOf and often totalSupply with ERC20, but has no decimals
+        if erc20 && !standards.contains(&TokenStandard::Erc721) {
+            standards.insert(0, TokenStandard::Erc20);
+        }
+
+        Ok(ContractKind::new(to_checksum(&parsed_address, None), AddressKind::Contract, standards, proxy))
+    }
+
+    async fn send_raw_transaction(&self, raw_transaction: &str) -> Result<BroadcastTransaction, BlockchainServiceError> {
+        let raw_transaction = raw_transaction.trim().parse::<Bytes>().map_err(|e| {
+            BlockchainServiceError::InvalidTransaction(format!("raw transaction is not hex encoded: {}", e))
+        })?;
+        let (transaction, from) = decode_signed_transaction(&raw_transaction)?;
+
+        // A transaction without chain id could be replayed on any chain, refuse to broadcast it
+        let expected_chain_id = self.expected_chain_id().await?;
+        let chain_id = match transaction.chain_id() {
+            Some(chain_id) if chain_id.as_u64() == expected_chain_id => expected_chain_id,
+            Some(chain_id) => {
+                return Err(BlockchainServiceError::InvalidTransaction(format!(
+                    "transaction is signed for chain id {}, chain {} has chain id {}", chain_id, self.chain, expected_chain_id
+                )));
+            }
+            None => return Err(BlockchainServiceError::InvalidTransaction("transaction has no chain id (not EIP-155 protected)".to_string())),
+        };
+
+        let to = transaction.to_addr().copied();
+        let erc20 = match to {
+            Some(to) => self.decode_erc20_intent(to, transaction.data()).await?,
+            None => None,
+        };
+        let transaction_type = match transaction {
+            TypedTransaction::Legacy(_) => "legacy",
+            TypedTransaction::Eip2930(_) => "eip2930",
+            TypedTransaction::Eip1559(_) => "eip1559",
+        };
+        let value = transaction.value().copied().unwrap_or_default();
+
+        // The hash is computed locally rather than trusted from whichever node answered
+        let hash = H256::from(keccak256(&raw_transaction));
+        self.upstream_pool.send_raw_transaction(&raw_transaction).await?;
+        info!("Broadcast transaction hash={:?} chain={} from={:?} to={:?}", hash, self.chain, from, to);
+
+        Ok(BroadcastTransaction {
+            hash: format!("{:?}", hash),
+            transaction_type: transaction_type.to_string(),
+            chain_id,
+            from: to_checksum(&from, None),
+            to: to.map(|to| to_checksum(&to, None)),
+            nonce: transaction.nonce().copied().unwrap_or_default().to_string(),
+            gas_limit: transaction.gas().copied().unwrap_or_default().to_string(),
+            value: format_units(value, NATIVE_DECIMALS)?,
+            value_raw: value.to_string(),
+            erc20,
+        })
+    }
+
+    async fn build_transaction(&self, request: TransactionBuildRequest) -> Result<UnsignedTransaction, BlockchainServiceError> {
+        let from = self.resolve_address(request.from.trim(), "sender", None).await?;
+        let (to, value, data) = self.intent_call(request.intent).await?;
+
+        let (chain_id, nonce, (base_fee, priority_fees)) = tokio::try_join!(
+            self.expected_chain_id(),
+            async {
+                let pending = BlockId::Number(BlockNumber::Pending);
+                self.provider.get_transaction_count(from, Some(pending)).await.map_err(BlockchainServiceError::EthProviderError)
+            },
+            self.fee_history_summary(),
+        )?;
+        // Index into PRIORITY_FEE_PERCENTILES
+        let percentile_index = match request.speed {
+            FeeSpeed::Slow => 0,
+            FeeSpeed::Standard => 1,
+            FeeSpeed::Fast => 2,
+        };
+        let priority_fee = priority_fees[percentile_index];
+        let max_fee = max_fee_per_gas(base_fee, priority_fee)?;
+
+        let mut transaction: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(from)
+            .to(to)
+            .value(value)
+            .data(data)
+            .chain_id(chain_id)
+            .nonce(nonce)
+            .max_priority_fee_per_gas(priority_fee)
+            .max_fee_per_gas(max_fee)
+            .into();
+
+        // A call that would revert cannot be built, report why instead
+        if request.access_list {
+            let access_list = self.provider.create_access_list(&transaction, None).await.map_err(explain_revert)?;
+            transaction.set_access_list(access_list.access_list);
+        }
+        // Estimated with the access list attached, since it changes the gas used
+        let gas_limit = self.provider.estimate_gas(&transaction, None).await.map_err(explain_revert)?;
+        transaction.set_gas(gas_limit);
+
+        let access_list = match (&transaction, request.access_list) {
+            (TypedTransaction::Eip1559(eip1559), true) => Some(
+                eip1559
+                    .access_list
+                    .0
+                    .iter()
+                    .map(|item| AccessListEntry {
+                        address: to_checksum(&item.address, None),
+                        storage_keys: item.storage_keys.iter().map(|key| format!("{:?}", key)).collect(),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        Ok(UnsignedTransaction {
+            chain_id,
+            from: to_checksum(&from, None),
+            to: to_checksum(&to, None),
+            nonce: nonce.to_string(),
+            gas_limit: gas_limit.to_string(),
+            max_fee_per_gas: max_fee.to_string(),
+            max_priority_fee_per_gas: priority_fee.to_string(),
+            value: value.to_string(),
+            data: transaction.data().cloned().unwrap_or_default().to_string(),
+            access_list,
+            signing_payload: transaction.rlp().to_string(),
+            signing_hash: format!("{:?}", transaction.sighash()),
+        })
+    }
+
+    async fn get_transaction(&self, hash: H256) -> Result<TransactionDetails, BlockchainServiceError> {
+        let (transaction, receipt) = tokio::try_join!(
+            self.provider.get_transaction(hash),
+            self.provider.get_transaction_receipt(hash),
+        )?;
+
+        let receipt = match (transaction, receipt) {
+            (_, Some(receipt)) => receipt,
+            (Some(transaction), None) => {
+                return Ok(TransactionDetails {
+                    hash: format!("{:?}", hash),
+                    status: TransactionStatus::Pending,
+                    from: to_checksum(&transaction.from, None),
+                    to: transaction.to.map(|to| to_checksum(&to, None)),
+                    contract_address: None,
+                    block_number: None,
+                    block_hash: None,
+                    gas_used: None,
+                    effective_gas_price: None,
+                    confirmations: None,
+                    erc20_logs: Vec::new(),
+                });
+            }
+            (None, None) => return Err(BlockchainServiceError::TransactionNotFound(format!("{:?}", hash))),
+        };
+
+        let (latest_block, erc20_logs) = tokio::try_join!(
+            async { self.provider.get_block_number().await.map_err(BlockchainServiceError::EthProviderError) },
+            self.decode_erc20_logs(&receipt.logs),
+        )?;
+        let block_number = receipt.block_number.map(|block_number| block_number.as_u64());
+        // Receipts of pre-Byzantium blocks carry a state root instead of a status, treat them as successful
+        let status = match receipt.status {
+            Some(status) if status.is_zero() => TransactionStatus::Failed,
+            _ => TransactionStatus::Success,
+        };
+
+        Ok(TransactionDetails {
+            hash: format!("{:?}", hash),
+            status,
+            from: to_checksum(&receipt.from, None),
+            to: receipt.to.map(|to| to_checksum(&to, None)),
+            contract_address: receipt.contract_address.map(|address| to_checksum(&address, None)),
+            block_number,
+            block_hash: receipt.block_hash.map(|block_hash| format!("{:?}", block_hash)),
+            gas_used: receipt.gas_used.map(|gas_used| gas_used.to_string()),
+            effective_gas_price: receipt.effective_gas_price.map(|price| price.to_string()),
+            confirmations: block_number.map(|block_number| latest_block.as_u64().saturating_sub(block_number) + 1),
+            erc20_logs,
+        })
+    }
+
+    async fn call_contract(&self, contract_address: &str, request: ContractCallRequest, block: Option<BlockId>) -> Result<ContractCallResult, BlockchainServiceError> {
+        let address = self.resolve_address(contract_address, "contract", block).await?;
+        let abi = match request.abi {
+            AbiSource::Stored(name) => self.abi_registry.get(&name).ok_or(BlockchainServiceError::AbiNotFound(name))?,
+            AbiSource::Inline(abi) => Arc::new(parse_abi_value(abi).map_err(|e| {
+                BlockchainServiceError::InvalidContractCall(format!("invalid ABI: {}", e))
+            })?),
+        };
+        let function = select_function(&abi, &request.function, request.args.len())?;
+        if request.args.len() != function.inputs.len() {
+            return Err(BlockchainServiceError::InvalidContractCall(format!(
+                "{} takes {} arguments, {} given", input_signature(function), function.inputs.len(), request.args.len()
+            )));
+        }
+
+        let args = function.inputs
+            .iter()
+            .zip(&request.args)
+            .enumerate()
+            .map(|(index, (input, arg))| json_to_token(&input.kind, arg).map_err(|e| {
+                let input_name = if input.name.is_empty() { index.to_string() } else { input.name.clone() };
+                BlockchainServiceError::InvalidContractCall(format!("argument {}: {}", input_name, e))
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data = function.encode_input(&args).map_err(|e| BlockchainServiceError::InvalidContractCall(e.to_string()))?;
+
+        let call = TransactionRequest::new().to(address).data(data).into();
+        let output = self.provider.call(&call, block).await.map_err(explain_revert)?;
+        // Calls to an address without code succeed with empty data
+        if output.is_empty() && !function.outputs.is_empty() && self.provider.get_code(address, block).await?.is_empty() {
+            return Err(BlockchainServiceError::NotAContract(to_checksum(&address, None)));
+        }
+        let values = function.decode_output(&output).map_err(|e| {
+            BlockchainServiceError::InvalidCallOutput(format!("{} returned {}: {}", input_signature(function), output, e))
+        })?;
+
+        Ok(ContractCallResult {
+            contract: to_checksum(&address, None),
+            function: input_signature(function),
+            outputs: function.outputs
+                .iter()
+                .zip(values)
+                .map(|(output, value)| ContractCallOutput {
+                    name: Some(output.name.clone()).filter(|name| !name.is_empty()),
+                    kind: output.kind.to_string(),
+                    value: token_to_json(value),
+                })
+                .collect(),
+        })
+    }
+
+    async fn lookup_names(&self, addresses: Vec<String>, block: Option<BlockId>) -> Result<Vec<Option<String>>, BlockchainServiceError> {
+        let parsed_addresses = self.resolve_addresses(&addresses, "wallet", block).await?;
+        let name_lookups = parsed_addresses
+            .into_iter()
+            .map(|address| self.lookup_ens_name(address, block))
+            .collect::<Vec<_>>();
+        stream::iter(name_lookups)
+            .buffered(self.max_concurrent_calls)
+            .try_collect()
+            .await
+    }
+
+    async fn get_multitoken_balances(&self, contract_address: &str, wallet_addresses: Vec<String>, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<MultiTokenBalance>, BlockchainServiceError> {
+        // Checked before resolving anything, the pairs all go into one balanceOfBatch call
+        let pairs = wallet_addresses.len().saturating_mul(token_ids.len());
+        if pairs > self.max_multitoken_pairs {
+            return Err(BlockchainServiceError::RequestTooLarge(format!(
+                "{} addresses x {} ids is {} pairs, at most {} are allowed",
+                wallet_addresses.len(), token_ids.len(), pairs, self.max_multitoken_pairs
+            )));
+        }
+
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+        let parsed_wallet_addresses = self.resolve_addresses(&wallet_addresses, "wallet", block).await?;
+        let wallet_addresses = checksum_addresses(&parsed_wallet_addresses);
+        let parsed_token_ids = parse_token_ids(&token_ids)?;
+
+        // Every address is paired with every id, so all balances come back from a single balanceOfBatch call
+        let (accounts, ids): (Vec<Address>, Vec<U256>) = parsed_wallet_addresses
+            .iter()
+            .flat_map(|address| parsed_token_ids.iter().map(move |token_id| (*address, *token_id)))
+            .unzip();
+        if accounts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let pair_count = accounts.len();
+
+        let contract = IERC1155::new(contract_address, self.provider.clone());
+        let balances = match at_block(contract.balance_of_batch(accounts, ids), block).call().await {
+            Ok(balances) => balances,
+            Err(e) => return Err(self.explain_contract_error(contract_address, block, e.into()).await),
+        };
+        // A non-conforming contract can answer with more or fewer balances than pairs, which would silently drop some
+        if balances.len() != pair_count {
+            return Err(BlockchainServiceError::InvalidCallOutput(format!(
+                "balanceOfBatch returned {} balances for {} address and id pairs",
+                balances.len(), pair_count
+            )));
+        }
+
+        // Balances are ordered address by address, with one entry per id
+        Ok(wallet_addresses
+            .into_iter()
+            .zip(balances.chunks(parsed_token_ids.len()))
+            .flat_map(|(address, balances)| {
+                parsed_token_ids
+                    .iter()
+                    .zip(balances)
+                    .map(move |(token_id, balance)| MultiTokenBalance::new(address.clone(), token_id.to_string(), balance.to_string()))
+            })
+            .collect())
+    }
+
+    async fn get_multitoken_uris(&self, contract_address: &str, token_ids: Vec<String>, block: Option<BlockId>) -> Result<Vec<MultiTokenUri>, BlockchainServiceError> {
+        let contract_address = self.resolve_address(contract_address, "contract", block).await?;
+        let parsed_token_ids = parse_token_ids(&token_ids)?;
+
+        let contract = IERC1155::new(contract_address, self.provider.clone());
+        let uri_calls = parsed_token_ids
+            .iter()
+            .map(|token_id| contract.uri(*token_id))
+            .collect::<Vec<_>>();
+        let uris = self.call_optional_batch(&uri_calls, block).await?;
+
+        Ok(parsed_token_ids
+            .into_iter()
+            .zip(uris)
+            .map(|(token_id, uri)| MultiTokenUri::new(token_id.to_string(), uri.map(|uri| substitute_token_id(&uri, token_id))))
+            .collect())
+    }
+}
+
+// Fetch decoded Transfer logs emitted in [from_block, to_block], ordered by block and log index.
+// With addresses, logs where any of them is the sender or the recipient are merged, self transfers appear once.
+pub(super) async fn fetch_transfer_logs(contract: &IERC20<EthProvider>, addresses: &[Address], from_block: u64, to_block: u64) -> Result<Vec<(TransferFilter, LogMeta)>, BlockchainServiceError> {
+    let transfer_event = || contract.transfer_filter().from_block(from_block).to_block(to_block);
+    let mut logs = if addresses.is_empty() {
+        transfer_event().query_with_meta().await?
+    } else {
+        let (sent_event, received_event) = (transfer_event().topic1(addresses.to_vec()), transfer_event().topic2(addresses.to_vec()));
+        let (mut sent, received) = tokio::try_join!(sent_event.query_with_meta(), received_event.query_with_meta())?;
+        sent.extend(received);
+        sent
+    };
+
+    logs.sort_by_key(|(_, meta)| (meta.block_number, meta.log_index));
+    logs.dedup_by_key(|(_, meta)| (meta.block_number, meta.log_index));
+    Ok(logs)
+}
+
+pub(super) fn to_token_transfer(transfer: TransferFilter, meta: LogMeta, decimals: u8) -> Result<TokenTransfer, BlockchainServiceError> {
+    Ok(TokenTransfer {
+        block_number: meta.block_number.as_u64(),
+        transaction_hash: format!("{:?}", meta.transaction_hash),
+        log_index: meta.log_index.as_u64(),
+        from: to_checksum(&transfer.from, None),
+        to: to_checksum(&transfer.to, None),
+        amount: format_units(transfer.value, u32::from(decimals))?,
+        amount_raw: transfer.value.to_string(),
+    })
+}
+
+// Decode a signed transaction and recover its sender from the signature
+fn decode_signed_transaction(raw_transaction: &Bytes) -> Result<(TypedTransaction, Address), BlockchainServiceError> {
+    let (transaction, signature) = TypedTransaction::decode_signed(&rlp::Rlp::new(raw_transaction)).map_err(|e| {
+        BlockchainServiceError::InvalidTransaction(format!("cannot decode the signed transaction: {}", e))
+    })?;
+    let from = signature.recover(transaction.sighash()).map_err(|e| {
+        BlockchainServiceError::InvalidTransaction(format!("cannot recover the sender: {}", e))
+    })?;
+    Ok((transaction, from))
+}
+
+// Convert a human-readable amount to the token's smallest unit.
+// Rejects digits past the token decimals rather than silently truncating them.
+fn parse_token_amount(amount: &str, decimals: u8) -> Result<U256, BlockchainServiceError> {
+    let amount = amount.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() && fraction.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+        return Err(BlockchainServiceError::InvalidAmount(format!("[{}] is not a non-negative decimal number", amount)));
+    }
+    if fraction.len() > usize::from(decimals) {
+        return Err(BlockchainServiceError::InvalidAmount(format!("[{}] has more than {} decimals", amount, decimals)));
+    }
+    parse_units(amount, u32::from(decimals))
+        .map(U256::from)
+        .map_err(|e| BlockchainServiceError::InvalidAmount(format!("[{}] {}", amount, e)))
+}
+
+// Reason of a reverted call, decoded from the revert data or taken from the node's message.
+// None when the call failed for another reason.
+fn revert_reason(e: &ContractError<EthProvider>) -> Option<String> {
+    match e {
+        ContractError::Revert(data) => Some(decode_revert_data(data).unwrap_or_else(|| "execution reverted".to_string())),
+        ContractError::MiddlewareError { e } | ContractError::ProviderError { e } => provider_revert_reason(e),
+        _ => None,
+    }
+}
+
+// Reverts carry their decoded reason, other provider errors are kept as they are
+fn explain_revert(e: ProviderError) -> BlockchainServiceError {
+    match provider_revert_reason(&e) {
+        Some(reason) => BlockchainServiceError::ExecutionReverted(reason),
+        None => BlockchainServiceError::EthProviderError(e),
+    }
+}
+
+fn provider_revert_reason(e: &ProviderError) -> Option<String> {
+    let rpc_error = RpcError::as_error_response(e).filter(|rpc_error| rpc_error.is_revert())?;
+    Some(rpc_error
+        .as_revert_data()
+        .and_then(|data| decode_revert_data(&data))
+        .unwrap_or_else(|| rpc_error.message.clone()))
+}
+
+// Error(string) reason, Panic(uint256) code or selector of a custom error
+fn decode_revert_data(data: &[u8]) -> Option<String> {
+    if let Some(reason) = String::decode_with_selector(data) {
+        return Some(reason);
+    }
+    match data.strip_prefix(&PANIC_SELECTOR) {
+        Some(code) if code.len() == 32 => Some(format!("panic code {:#x}", U256::from_big_endian(code))),
+        _ if data.len() >= 4 => Some(format!("custom error 0x{}", hex::encode(&data[..4]))),
+        _ => None,
+    }
+}
+
+// name(type,...) as used for selectors and to pick an overload
+fn input_signature(function: &Function) -> String {
+    let inputs = function.inputs.iter().map(|input| input.kind.to_string()).collect::<Vec<_>>();
+    format!("{}({})", function.name, inputs.join(","))
+}
+
+// Function called by name, or by full signature when the name is overloaded.
+// A bare name picks the overload taking as many arguments as were given.
+fn select_function<'a>(abi: &'a Abi, function: &str, arg_count: usize) -> Result<&'a Function, BlockchainServiceError> {
+    let function = function.trim();
+    let name = function.split('(').next().unwrap_or_default().trim();
+    let overloads = abi.functions_by_name(name).map_err(|_| {
+        BlockchainServiceError::InvalidContractCall(format!("ABI has no function {}", name))
+    })?;
+
+    let candidates = if function.contains('(') {
+        let signature = function.replace(' ', "");
+        overloads.iter().filter(|overload| input_signature(overload) == signature).collect::<Vec<_>>()
+    } else if overloads.len() == 1 {
+        overloads.iter().collect()
+    } else {
+        overloads.iter().filter(|overload| overload.inputs.len() == arg_count).collect()
+    };
+    match candidates.as_slice() {
+        [selected] => Ok(selected),
+        [] if function.contains('(') => Err(BlockchainServiceError::InvalidContractCall(format!("ABI has no function {}", function))),
+        [] => Err(BlockchainServiceError::InvalidContractCall(format!("no overload of {} takes {} arguments", name, arg_count))),
+        _ => Err(BlockchainServiceError::InvalidContractCall(format!(
+            "{} is overloaded, call it by signature such as {}", name, input_signature(candidates[0])
+        ))),
+    }
+}
+
+// Convert a JSON argument to the ABI type of its input.
+// Integers are JSON numbers or decimal strings (0x-prefixed hex for unsigned), bytes are 0x-prefixed hex,
+// arrays and tuples are JSON arrays.
+fn json_to_token(kind: &ParamType, value: &serde_json::Value) -> Result<Token, String> {
+    use serde_json::Value;
+
+    match (kind, value) {
+        (ParamType::Address, Value::String(address)) => address
+            .trim()
+            .parse::<Address>()
+            .map(Token::Address)
+            .map_err(|_| format!("[{}] is not an address", address)),
+        (ParamType::Bool, Value::Bool(value)) => Ok(Token::Bool(*value)),
+        (ParamType::String, Value::String(value)) => Ok(Token::String(value.clone())),
+        (ParamType::Bytes, Value::String(bytes)) => parse_hex_bytes(bytes).map(Token::Bytes),
+        (ParamType::FixedBytes(size), Value::String(bytes)) => match parse_hex_bytes(bytes)? {
+            bytes if bytes.len() == *size => Ok(Token::FixedBytes(bytes)),
+            bytes => Err(format!("expected {} bytes, got {}", size, bytes.len())),
+        },
+        (ParamType::Uint(size), Value::Number(_) | Value::String(_)) => {
+            let text = value.as_str().map(str::trim).map(str::to_string).unwrap_or_else(|| value.to_string());
+            let parsed = match text.strip_prefix("0x") {
+                Some(hex) => U256::from_str_radix(hex, 16).ok(),
+                None => U256::from_dec_str(&text).ok(),
+            };
+            match parsed {
+                Some(parsed) if parsed.bits() <= *size => Ok(Token::Uint(parsed)),
+                Some(_) => Err(format!("[{}] does not fit uint{}", text, size)),
+                None => Err(format!("[{}] is not an unsigned integer", text)),
+            }
+        }
+        (ParamType::Int(size), Value::Number(_) | Value::String(_)) => {
+            let text = value.as_str().map(str::trim).map(str::to_string).unwrap_or_else(|| value.to_string());
+            let parsed = I256::from_dec_str(&text).map_err(|_| format!("[{}] is not an integer", text))?;
+            // Values of intN lie in [-2^(N-1), 2^(N-1))
+            if (1..256).contains(size) {
+                let bound = I256::from_raw(U256::one() << (size - 1));
+                if parsed >= bound || parsed < -bound {
+                    return Err(format!("[{}] does not fit int{}", text, size));
+                }
+            }
+            Ok(Token::Int(parsed.into_raw()))
+        }
+        (ParamType::Array(item_kind), Value::Array(items)) => items
+            .iter()
+            .map(|item| json_to_token(item_kind, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Token::Array),
+        (ParamType::FixedArray(item_kind, length), Value::Array(items)) if items.len() == *length => items
+            .iter()
+            .map(|item| json_to_token(item_kind, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Token::FixedArray),
+        (ParamType::Tuple(kinds), Value::Array(items)) if items.len() == kinds.len() => kinds
+            .iter()
+            .zip(items)
+            .map(|(kind, item)| json_to_token(kind, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Token::Tuple),
+        (kind, value) => Err(format!("{} is not a valid {}", value, kind)),
+    }
+}
+
+fn parse_hex_bytes(bytes: &str) -> Result<Vec<u8>, String> {
+    bytes.trim().parse::<Bytes>().map(|bytes| bytes.to_vec()).map_err(|_| format!("[{}] is not hex encoded", bytes))
+}
+
+// JSON form of a decoded output, integers as decimal strings since they may exceed JSON number precision
+fn token_to_json(token: Token) -> serde_json::Value {
+    use serde_json::Value;
+
+    match token {
+        Token::Address(address) => Value::String(to_checksum(&address, None)),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => Value::String(format!("0x{}", hex::encode(bytes))),
+        Token::Uint(value) => Value::String(value.to_string()),
+        Token::Int(value) => Value::String(I256::from_raw(value).to_string()),
+        Token::Bool(value) => Value::Bool(value),
+        Token::String(value) => Value::String(value),
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => Value::Array(items.into_iter().map(token_to_json).collect()),
+    }
+}
+
+// Twice the base fee plus the priority fee, so the transaction stays includable through several full blocks.
+// Both fees come from the node, which may answer anything.
+fn max_fee_per_gas(base_fee: U256, priority_fee: U256) -> Result<U256, BlockchainServiceError> {
+    base_fee
+        .checked_mul(U256::from(2))
+        .and_then(|doubled_base_fee| doubled_base_fee.checked_add(priority_fee))
+        .ok_or_else(|| BlockchainServiceError::InvalidFeeData(format!("base fee {} with priority fee {} overflows the max fee", base_fee, priority_fee)))
+}
+
+fn gas_cost(gas_limit: U256, fee_per_gas: U256) -> Result<U256, BlockchainServiceError> {
+    gas_limit
+        .checked_mul(fee_per_gas)
+        .ok_or_else(|| BlockchainServiceError::InvalidFeeData(format!("{} gas at {} per gas overflows the cost", gas_limit, fee_per_gas)))
+}
+
+// Median over blocks of the priority fee at each requested percentile.
+// Empty blocks report zero rewards and are left out, so quiet periods do not drag the suggestion to zero.
+fn median_priority_fees(rewards: &[Vec<U256>]) -> Vec<U256> {
+    (0..PRIORITY_FEE_PERCENTILES.len())
+        .map(|index| {
+            let mut fees = rewards
+                .iter()
+                .filter_map(|block_rewards| block_rewards.get(index).copied())
+                .filter(|fee| !fee.is_zero())
+                .collect::<Vec<_>>();
+            fees.sort();
+            fees.get(fees.len() / 2).copied().unwrap_or_default()
+        })
+        .collect()
+}
+
+// Result of a raw eth_call, None when the call reverted
+async fn optional_call(call: impl std::future::Future<Output = Result<Bytes, ProviderError>>) -> Result<Option<Bytes>, BlockchainServiceError> {
+    match call.await {
+        Ok(data) => Ok(Some(data)),
+        Err(e) => {
+            let e = BlockchainServiceError::EthProviderError(e);
+            if e.is_revert() {
+                Ok(None)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+// Decode a string getter result, either an ABI string or a null padded bytes32 as returned by MKR-style tokens
+fn decode_metadata_string(data: &Bytes) -> Option<String> {
+    if let Ok(mut tokens) = ethers::abi::decode(&[ParamType::String], data) {
+        match tokens.pop() {
+            Some(Token::String(value)) if !value.is_empty() => return Some(value),
+            _ => {}
+        }
+    }
+    if data.len() != 32 {
+        return None;
+    }
+
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    match std::str::from_utf8(&data[..end]) {
+        Ok(value) if !value.is_empty() => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+// An ABI string, None for empty strings and data that does not decode
+fn decode_string(data: &Bytes) -> Option<String> {
+    match ethers::abi::decode(&[ParamType::String], data).ok()?.pop() {
+        Some(Token::String(value)) if !value.is_empty() => Some(value),
+        _ => None,
+    }
+}
+
+fn decode_bool(data: &Bytes) -> Option<bool> {
+    match ethers::abi::decode(&[ParamType::Bool], data).ok()?.pop() {
+        Some(Token::Bool(value)) => Some(value),
+        _ => None,
+    }
+}
+
+// A zero-padded address word, ethabi itself ignores dirty padding
+fn decode_address(data: &Bytes) -> Option<Address> {
+    if data.len() < 32 || data[..12].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    match ethers::abi::decode(&[ParamType::Address], data).ok()?.pop() {
+        Some(Token::Address(address)) if !address.is_zero() => Some(address),
+        _ => None,
+    }
+}
+
+fn decode_decimals(data: &Bytes) -> Option<u8> {
+    if data.len() < 32 {
+        return None;
+    }
+    u8::try_from(U256::from_big_endian(&data[..32])).ok()
+}
+
+// Responses always carry EIP-55 checksummed addresses, whatever casing or ENS name was given
+fn checksum_addresses(addresses: &[Address]) -> Vec<String> {
+    addresses.iter().map(|address| to_checksum(address, None)).collect()
+}
+
+// All lowercase and all uppercase addresses carry no checksum, mixed case ones must match EIP-55
+fn has_valid_checksum(input: &str, address: &Address) -> bool {
+    let hex = input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")).unwrap_or(input);
+    let has_lowercase = hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_uppercase = hex.chars().any(|c| c.is_ascii_uppercase());
+    !(has_lowercase && has_uppercase) || to_checksum(address, None)[2..] == *hex
+}
+
+// Pin a contract call to the given block, or leave it reading at `latest`
+fn at_block<D: Detokenize>(call: ContractCall<EthProvider, D>, block: Option<BlockId>) -> ContractCall<EthProvider, D> {
+    match block {
+        Some(block) => call.block(block),
+        None => call,
+    }
+}
+
+fn is_latest(block: Option<BlockId>) -> bool {
+    matches!(block, None | Some(BlockId::Number(BlockNumber::Latest)))
+}
+
+// Token ids are uint256, given in decimal or as 0x-prefixed hex
+fn parse_token_ids(token_ids: &[String]) -> Result<Vec<U256>, BlockchainServiceError> {
+    token_ids
+        .iter()
+        .map(|token_id| {
+            let parsed = match token_id.strip_prefix("0x").or_else(|| token_id.strip_prefix("0X")) {
+                Some(hex) => U256::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+                None => U256::from_dec_str(token_id).map_err(|e| e.to_string()),
+            };
+            parsed.map_err(|e| BlockchainServiceError::InvalidTokenId(format!("[{}] {}", token_id, e)))
+        })
+        .collect()
+}
+
+// EIP-1967 storage slots are keccak256 of a label minus one, so they cannot collide with Solidity's layout
+fn eip1967_slot(label: &str) -> H256 {
+    H256::from_uint(&(U256::from_big_endian(&keccak256(label)) - 1))
+}
+
+// Address kept in the low 20 bytes of a storage slot, None when the slot is unset
+fn slot_address(slot: H256) -> Option<Address> {
+    let address = Address::from_slice(&slot.as_bytes()[12..]);
+    (!address.is_zero()).then_some(address)
+}
+
+fn eip1167_implementation(code: &Bytes) -> Option<Address> {
+    let implementation = code.strip_prefix(&EIP1167_CODE_PREFIX)?.strip_suffix(&EIP1167_CODE_SUFFIX)?;
+    (implementation.len() == 20).then(|| Address::from_slice(implementation))
+}
+
+// EIP-1155 metadata URIs may contain `{id}`, to be replaced by the id as 64 lowercase hex digits without 0x
+fn substitute_token_id(uri: &str, token_id: U256) -> String {
+    uri.replace("{id}", &format!("{:064x}", token_id))
+}
+
+// Express `balance` as a percentage of `total_supply`, truncated to SHARE_OF_SUPPLY_DECIMALS places.
+// Returns None when the share is undefined, i.e. the token has no supply.
+fn format_share_of_supply(balance: U256, total_supply: U256) -> Result<Option<String>, BlockchainServiceError> {
+    if total_supply.is_zero() {
+        return Ok(None);
+    }
+
+    // Scale by 100 for percent plus the kept decimals, in 512 bits so large balances cannot overflow
+    let scaled = balance.full_mul(U256::exp10(SHARE_OF_SUPPLY_DECIMALS as usize + 2)) / U512::from(total_supply);
+    match U256::try_from(scaled) {
+        Ok(share) => Ok(Some(format_units(share, SHARE_OF_SUPPLY_DECIMALS)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::{aggregate3_calls, aggregate3_results, call_data, client_for, client_with, erc20_metadata, selector, stub_contract_node, stub_node, uint};
+
+    const REVERTED: &str = "execution reverted";
+    const CONTRACT: &str = "0x000000000000000000000000000000000000c0DE";
+    const EOA: &str = "0x0000000000000000000000000000000000000e0A";
+    const OWNER: &str = "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe";
+
+    const MULTICALL: &str = "multicall_address = \"0xcA11bde05977b3631167028862bE2a173976CA11\"";
+
+    // Token with 6 decimals whose balanceOf(owner) is the owner's low bytes in whole tokens, and which lets
+    // spender 0x…2222 move 1.5 tokens of any owner and every other spender an unlimited amount
+    fn token_contract(data: &[u8]) -> Vec<u8> {
+        if let Some(output) = erc20_metadata(data, 6) {
+            return output;
+        }
+        match selector(data).as_str() {
+            "70a08231" => uint(U256::from(&data[data.len() - 8..]) * U256::exp10(6)),
+            "dd62ed3e" if data.ends_with(&[0x22, 0x22]) => uint(U256::from(1_500_000)),
+            "dd62ed3e" => uint(U256::MAX),
+            selector => panic!("unexpected call {}", selector),
+        }
+    }
+
+    // Node of token_contract that records the selectors of every eth_call, an aggregate3 one as "aggregate3"
+    // followed by the selectors it batches
+    async fn recording_token_node(calls: Arc<std::sync::Mutex<Vec<Vec<String>>>>) -> String {
+        stub_node(move |_, params| {
+            let data = call_data(params);
+            let output = match aggregate3_calls(&data) {
+                Some(inner_calls) => {
+                    let mut selectors = vec!["aggregate3".to_string()];
+                    selectors.extend(inner_calls.iter().map(|inner_call| selector(inner_call)));
+                    calls.lock().unwrap().push(selectors);
+                    aggregate3_results(inner_calls.iter().map(|inner_call| token_contract(inner_call)).collect())
+                }
+                None => {
+                    calls.lock().unwrap().push(vec![selector(&data)]);
+                    token_contract(&data)
+                }
+            };
+            Ok(json!(Bytes::from(output)))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_account_balances_batch_through_multicall() {
+        let wallets = ["0x0000000000000000000000000000000000000003", "0x0000000000000000000000000000000000000001", "0x0000000000000000000000000000000000000002"]
+            .map(String::from)
+            .to_vec();
+        let token = "0x0000000000000000000000000000000000001111";
+        let balance_of = |calls: &[Vec<String>]| calls.iter().flatten().filter(|selector| *selector == "70a08231").count();
+
+        // Every balanceOf goes into a single aggregate3 call, results keep the order of the addresses
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let multicall_client = client_with(&recording_token_node(calls.clone()).await, MULTICALL);
+        let balances = multicall_client.get_account_balance(token, wallets.clone(), false, None).await.unwrap();
+        assert_eq!(balances.iter().map(|balance| balance.balance.as_str()).collect::<Vec<_>>(), ["3.000000", "1.000000", "2.000000"]);
+        assert_eq!(balances[0].address, "0x0000000000000000000000000000000000000003");
+        let calls = calls.lock().unwrap().clone();
+        let balance_batches = calls.iter().filter(|call| call.iter().any(|selector| selector == "70a08231")).collect::<Vec<_>>();
+        assert_eq!(balance_batches.len(), 1);
+        assert_eq!(balance_batches[0][0], "aggregate3");
+        assert_eq!(balance_of(&calls), 3);
+
+        // Without multicall every balanceOf is its own eth_call, still in address order
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = client_with(&recording_token_node(calls.clone()).await, "max_concurrent_calls = 2");
+        let balances = client.get_account_balance(token, wallets, false, None).await.unwrap();
+        assert_eq!(balances.iter().map(|balance| balance.balance_raw.as_str()).collect::<Vec<_>>(), ["3000000", "1000000", "2000000"]);
+        let calls = calls.lock().unwrap().clone();
+        assert!(calls.iter().all(|call| call[0] != "aggregate3"));
+        assert_eq!(balance_of(&calls), 3);
+    }
+
+    #[test]
+    fn test_format_share_of_supply() {
+        let total_supply = U256::exp10(24);
+
+        assert_eq!(format_share_of_supply(U256::exp10(23), total_supply).unwrap().unwrap(), "10.000000");
+        assert_eq!(format_share_of_supply(U256::from(1), U256::from(3)).unwrap().unwrap(), "33.333333");
+        assert_eq!(format_share_of_supply(U256::zero(), total_supply).unwrap().unwrap(), "0.000000");
+        assert_eq!(format_share_of_supply(U256::MAX, U256::MAX).unwrap().unwrap(), "100.000000");
+        assert!(format_share_of_supply(U256::from(1), U256::zero()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_native_balances_skip_multicall() {
+        // Balances come from eth_getBalance even with Multicall3 configured, the stub answers no eth_call
+        let provider_url = stub_node(|method, params| match method {
+            "eth_getBalance" if params[0] == "0xd87d2676b8bbd7d4bf7884089356f7bb82158cfe" => Ok(json!(U256::exp10(18))),
+            "eth_getBalance" => Ok(json!(U256::exp10(17) * 25)),
+            method => panic!("unexpected {}", method),
+        })
+        .await;
+        let client = client_with(&provider_url, MULTICALL);
+        let addresses = vec![
+            "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe".to_string(),
+            "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2".to_string(),
+        ];
+        let balances = client.get_native_balance(addresses, Some(BlockId::Number(BlockNumber::Number(1.into())))).await.unwrap();
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[0].balance, "1.000000000000000000");
+        assert_eq!(balances[1].address, "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2");
+        assert_eq!(balances[1].balance_raw, "2500000000000000000");
+    }
+
+    #[tokio::test]
+    async fn test_ens_names_resolve_at_requested_block() {
+        // Registry, resolver and balance reads must all happen at block 5
+        let provider_url = stub_node(|method, params| {
+            assert_eq!(params[1], "0x5", "{}", method);
+            match method {
+                "eth_call" => match selector(&call_data(params)).as_str() {
+                    "0178b8bf" => Ok(json!(Bytes::from(ethers::abi::encode(&[Token::Address(Address::from_low_u64_be(0xe25))])))),
+                    "3b3b57de" => Ok(json!(Bytes::from(ethers::abi::encode(&[Token::Address(Address::from_low_u64_be(0xa11ce))])))),
+                    selector => panic!("unexpected call {}", selector),
+                },
+                "eth_getBalance" => {
+                    assert_eq!(params[0], "0x00000000000000000000000000000000000a11ce");
+                    Ok(json!(U256::exp10(18)))
+                }
+                method => panic!("unexpected {}", method),
+            }
+        })
+        .await;
+        let client = client_for(&provider_url);
+        let block = Some(BlockId::Number(BlockNumber::Number(5.into())));
+        let balances = client.get_native_balance(vec!["alice.eth".to_string()], block).await.unwrap();
+        assert_eq!(balances[0].address, "0x00000000000000000000000000000000000A11cE");
+        assert_eq!(balances[0].balance, "1.000000000000000000");
+    }
+
+    // Serve a node with code deployed at CONTRACT only, answering its eth_calls with `contract(calldata)`.
+    // Calls to any other address succeed with empty output, as they do on an account without code.
+    async fn stub_deployed_contract<F>(contract: F) -> String
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>, String> + Clone + Send + Sync + 'static,
+    {
+        let deployed = |address: &serde_json::Value| address.as_str().is_some_and(|address| address.eq_ignore_ascii_case(CONTRACT));
+        stub_node(move |method, params| match method {
+            "eth_getCode" if deployed(&params[0]) => Ok(json!("0x6080")),
+            "eth_getCode" => Ok(json!("0x")),
+            "eth_getStorageAt" => Ok(json!(H256::zero())),
+            "eth_call" if deployed(&params[0]["to"]) => contract(&call_data(params)).map(|output| json!(Bytes::from(output))),
+            "eth_call" => Ok(json!("0x")),
+            method => panic!("unexpected {}", method),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_nft_reverts_and_missing_contract() {
+        let provider_url = stub_deployed_contract(|data| match selector(data).as_str() {
+            // ownerOf reverts for the burnt token 2
+            "6352211e" if data.ends_with(&[2]) => Err(REVERTED.to_string()),
+            "6352211e" => Ok(ethers::abi::encode(&[Token::Address(OWNER.parse().unwrap())])),
+            "70a08231" => Err(REVERTED.to_string()),
+            selector => panic!("unexpected call {}", selector),
+        })
+        .await;
+        let client = client_for(&provider_url);
+
+        let owners = client.get_nft_owners(CONTRACT, vec!["1".to_string(), "2".to_string()], None).await.unwrap();
+        assert_eq!(owners.iter().map(|owner| owner.owner.as_deref()).collect::<Vec<_>>(), [Some(OWNER), None]);
+
+        let result = client.get_nft_balances(CONTRACT, vec![OWNER.to_string()], None).await;
+        assert!(result.unwrap_err().is_revert());
+        let result = client.get_nft_balances(EOA, vec![OWNER.to_string()], None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::NotAContract(address)) if address == EOA));
+    }
+
+    #[tokio::test]
+    async fn test_multitoken_balances_pair_limit() {
+        let client = client_with("http://127.0.0.1:1", "max_multitoken_pairs = 4");
+        let addresses = vec![
+            "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe".to_string(),
+            "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2".to_string(),
+        ];
+        let ids = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let result = client.get_multitoken_balances("0xcA11bde05977b3631167028862bE2a173976CA11", addresses, ids, None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::RequestTooLarge(_))));
+        assert_eq!(result.unwrap_err().code(), "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn test_multitoken_balances_reject_short_batch() {
+        // A contract answering balanceOfBatch with one balance less than the pairs it was asked for
+        let provider_url = stub_contract_node(|data| match selector(data).as_str() {
+            "4e1273f4" => ethers::abi::encode(&[Token::Array(vec![Token::Uint(U256::from(5)); 5])]),
+            selector => panic!("unexpected call {}", selector),
+        })
+        .await;
+        let client = client_for(&provider_url);
+        let addresses = vec![
+            "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe".to_string(),
+            "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2".to_string(),
+        ];
+        let ids = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let result = client.get_multitoken_balances("0xcA11bde05977b3631167028862bE2a173976CA11", addresses, ids, None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::InvalidCallOutput(_))));
+        assert_eq!(result.unwrap_err().code(), "invalid_call_output");
+    }
+
+    #[tokio::test]
+    async fn test_multitoken_reverts_and_missing_contract() {
+        let provider_url = stub_deployed_contract(|data| match selector(data).as_str() {
+            "4e1273f4" => Err(REVERTED.to_string()),
+            // uri reverts for the unknown token 2
+            "0e89341c" if data.ends_with(&[2]) => Err(REVERTED.to_string()),
+            "0e89341c" => Ok(ethers::abi::encode(&[Token::String("ipfs://tokens/{id}.json".to_string())])),
+            selector => panic!("unexpected call {}", selector),
+        })
+        .await;
+        let client = client_for(&provider_url);
+        let ids = vec!["1".to_string(), "2".to_string()];
+
+        let uris = client.get_multitoken_uris(CONTRACT, ids.clone(), None).await.unwrap();
+        assert_eq!(uris[0].uri.as_deref(), Some("ipfs://tokens/0000000000000000000000000000000000000000000000000000000000000001.json"));
+        assert_eq!(uris[1].uri, None);
+
+        let result = client.get_multitoken_balances(CONTRACT, vec![OWNER.to_string()], ids.clone(), None).await;
+        assert!(result.unwrap_err().is_revert());
+        let result = client.get_multitoken_balances(EOA, vec![OWNER.to_string()], ids, None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::NotAContract(address)) if address == EOA));
+    }
+
+    #[tokio::test]
+    async fn test_contract_kind_of_reverting_contract_and_account() {
+        // A contract reverting every probe implements no known standard, an address without code is an account
+        let provider_url = stub_deployed_contract(|_| Err(REVERTED.to_string())).await;
+        let client = client_for(&provider_url);
+
+        let contract = client.get_contract_kind(CONTRACT, None).await.unwrap();
+        assert_eq!((contract.kind, contract.standards.len()), (AddressKind::Contract, 0));
+        assert!(contract.proxy.is_none());
+        let account = client.get_contract_kind(EOA, None).await.unwrap();
+        assert_eq!((account.address.as_str(), account.kind), (EOA, AddressKind::Eoa));
+    }
+
+    #[tokio::test]
+    async fn test_call_contract_reverts_and_missing_contract() {
+        let provider_url = stub_deployed_contract(|_| Err(REVERTED.to_string())).await;
+        let client = client_for(&provider_url);
+        let request = || ContractCallRequest {
+            abi: AbiSource::Inline(json!([
+                { "type": "function", "name": "owner", "stateMutability": "view", "inputs": [], "outputs": [{ "name": "", "type": "address" }] },
+            ])),
+            function: "owner".to_string(),
+            args: Vec::new(),
+        };
+
+        let result = client.call_contract(CONTRACT, request(), None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::ExecutionReverted(reason)) if reason == REVERTED));
+        let result = client.call_contract(EOA, request(), None).await;
+        assert!(matches!(result, Err(BlockchainServiceError::NotAContract(address)) if address == EOA));
+    }
+
+    #[test]
+    fn test_parse_token_ids() {
+        let token_ids = vec!["42".to_string(), "0x2a".to_string()];
+        assert_eq!(parse_token_ids(&token_ids).unwrap(), vec![U256::from(42), U256::from(42)]);
+        assert!(parse_token_ids(&["-1".to_string()]).is_err());
+        assert!(parse_token_ids(&["0xzz".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_decode_signed_transaction() {
+        use ethers::signers::{LocalWallet, Signer};
+        use ethers::types::Eip1559TransactionRequest;
+
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse::<LocalWallet>().unwrap().with_chain_id(21u64);
+        let token = Address::from_low_u64_be(0x1111);
+        let calldata = TransferCall { recipient: Address::from_low_u64_be(0x2222), amount: U256::exp10(18) };
+        let transaction: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(token)
+            .nonce(7)
+            .gas(60_000)
+            .chain_id(21)
+            .data(ethers::abi::AbiEncode::encode(calldata.clone()))
+            .into();
+        let signature = wallet.sign_transaction_sync(&transaction).unwrap();
+
+        let (decoded, from) = decode_signed_transaction(&transaction.rlp_signed(&signature)).unwrap();
+        assert_eq!(from, wallet.address());
+        assert_eq!(decoded.chain_id().map(|chain_id| chain_id.as_u64()), Some(21));
+        assert_eq!(decoded.to_addr(), Some(&token));
+        assert!(matches!(IERC20Calls::decode(decoded.data().unwrap()), Ok(IERC20Calls::Transfer(call)) if call == calldata));
+
+        assert!(decode_signed_transaction(&Bytes::from(vec![0x02, 0xc0])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_transfer_fee_overflow() {
+        // Base fee past half of U256::MAX, so twice of it does not fit
+        let provider_url = stub_node(|method, params| {
+            Ok(match method {
+                "eth_call" => json!(Bytes::from(token_contract(&call_data(params)))),
+                "eth_feeHistory" => json!({
+                    "oldestBlock": "0x1",
+                    "baseFeePerGas": ["0x1", format!("{:#x}", U256::MAX / 2 + 1)],
+                    "gasUsedRatio": [0.5],
+                    "reward": [["0x1", "0x2", "0x3"]],
+                }),
+                "eth_estimateGas" => json!("0xfde8"),
+                method => panic!("unexpected {}", method),
+            })
+        })
+        .await;
+        let client = client_for(&provider_url);
+
+        let result = client
+            .estimate_transfer(
+                "0xcA11bde05977b3631167028862bE2a173976CA11",
+                "0xD87d2676B8bbd7d4bf7884089356F7BB82158cFe",
+                "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2",
+                "1.5",
+            )
+            .await;
+        assert!(matches!(result, Err(BlockchainServiceError::InvalidFeeData(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_build_transaction_signing_payload() {
+        use ethers::signers::{LocalWallet, Signer};
+
+        // Node on chain 21 where the sender's next nonce is 7, the next base fee 10 gwei and a transfer uses 21000 gas
+        let provider_url = stub_node(|method, _| {
+            Ok(match method {
+                "eth_chainId" => json!("0x15"),
+                "eth_getTransactionCount" => json!("0x7"),
+                "eth_feeHistory" => json!({
+                    "oldestBlock": "0x1",
+                    "baseFeePerGas": ["0x2540be400", "0x2540be400"],
+                    "gasUsedRatio": [0.5],
+                    "reward": [["0x3b9aca00", "0x77359400", "0xb2d05e00"]],
+                }),
+                "eth_estimateGas" => json!("0x5208"),
+                method => panic!("unexpected {}", method),
+            })
+        })
+        .await;
+
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse::<LocalWallet>().unwrap();
+        let client = client_for(&provider_url);
+        let request = TransactionBuildRequest {
+            from: to_checksum(&wallet.address(), None),
+            intent: TransactionIntent::NativeTransfer { to: "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2".to_string(), amount: "1.5".to_string() },
+            speed: FeeSpeed::Standard,
+            access_list: false,
+        };
+        let unsigned = client.build_transaction(request).await.unwrap();
+        assert_eq!((unsigned.chain_id, unsigned.nonce.as_str(), unsigned.gas_limit.as_str()), (21, "7", "21000"));
+        assert_eq!(unsigned.value, "1500000000000000000");
+        assert_eq!(unsigned.max_priority_fee_per_gas, "2000000000");
+
+        // EIP-2718 typed payload, whose hash is what gets signed
+        let signing_payload = unsigned.signing_payload.parse::<Bytes>().unwrap();
+        assert_eq!(signing_payload[0], 0x02);
+        let signing_hash = unsigned.signing_hash.parse::<H256>().unwrap();
+        assert_eq!(signing_hash, H256::from(keccak256(&signing_payload)));
+
+        // A signature over the hash completes the transaction described by the returned fields
+        let mut signature = wallet.sign_hash(signing_hash).unwrap();
+        // Typed transactions carry the y-parity rather than a legacy 27/28 v
+        signature.v -= 27;
+        let transaction: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(unsigned.to.parse::<Address>().unwrap())
+            .value(U256::from_dec_str(&unsigned.value).unwrap())
+            .data(unsigned.data.parse::<Bytes>().unwrap())
+            .chain_id(unsigned.chain_id)
+            .nonce(U256::from_dec_str(&unsigned.nonce).unwrap())
+            .gas(U256::from_dec_str(&unsigned.gas_limit).unwrap())
+            .max_priority_fee_per_gas(U256::from_dec_str(&unsigned.max_priority_fee_per_gas).unwrap())
+            .max_fee_per_gas(U256::from_dec_str(&unsigned.max_fee_per_gas).unwrap())
+            .into();
+        assert_eq!(transaction.sighash(), signing_hash);
+
+        let (decoded, from) = decode_signed_transaction(&transaction.rlp_signed(&signature)).unwrap();
+        assert_eq!(to_checksum(&from, None), unsigned.from);
+        assert_eq!(decoded.chain_id().map(|chain_id| chain_id.as_u64()), Some(unsigned.chain_id));
+        assert_eq!(decoded.nonce().map(U256::to_string), Some(unsigned.nonce));
+        assert_eq!(decoded.gas().map(U256::to_string), Some(unsigned.gas_limit));
+        assert_eq!(decoded.value().map(U256::to_string), Some(unsigned.value));
+        assert_eq!(decoded.to_addr().map(|to| to_checksum(to, None)), Some(unsigned.to));
+        let TypedTransaction::Eip1559(decoded) = decoded else { panic!("expected an EIP-1559 transaction") };
+        assert_eq!(decoded.max_fee_per_gas.map(|fee| fee.to_string()), Some(unsigned.max_fee_per_gas));
+        assert_eq!(decoded.max_priority_fee_per_gas.map(|fee| fee.to_string()), Some(unsigned.max_priority_fee_per_gas));
+    }
+
+    #[tokio::test]
+    async fn test_get_allowances_formats_each_spender() {
+        let client = client_for(&stub_contract_node(token_contract).await);
+        let owner = Address::from_low_u64_be(0x1234);
+        let spenders = [Address::from_low_u64_be(0x2222), Address::from_low_u64_be(0x3333)];
+
+        let allowances = client
+            .get_allowances(
+                "0x0000000000000000000000000000000000001111",
+                &format!("{:?}", owner),
+                spenders.iter().map(|spender| format!("{:?}", spender)).collect(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(allowances.len(), 2);
+        for (allowance, spender) in allowances.iter().zip(spenders) {
+            assert_eq!(allowance.owner, to_checksum(&owner, None));
+            assert_eq!(allowance.spender, to_checksum(&spender, None));
+        }
+        // Formatted with the token decimals, in the order the spenders were given
+        assert_eq!((allowances[0].allowance.as_str(), allowances[0].allowance_raw.as_str()), ("1.500000", "1500000"));
+        assert_eq!(allowances[1].allowance_raw, U256::MAX.to_string());
+        assert_eq!(allowances[1].allowance, format_units(U256::MAX, 6).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_token_metadata_cache_only_holds_latest_reads() {
+        let client = client_for(&stub_contract_node(token_contract).await);
+        let token = "0x0000000000000000000000000000000000001111";
+
+        let historical = Some(BlockId::Number(BlockNumber::Number(5.into())));
+        assert_eq!(client.get_token_metadata(token, historical).await.unwrap().symbol.as_deref(), Some("TKN"));
+        assert_eq!(client.token_cache.stats().entries, 0);
+
+        client.get_token_metadata(token, Some(BlockId::Number(BlockNumber::Latest))).await.unwrap();
+        client.get_token_metadata(token, historical).await.unwrap();
+        let stats = client.token_cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 0, 1));
+        client.get_token_metadata(token, None).await.unwrap();
+        assert_eq!(client.token_cache.stats().hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_decode_erc20_logs_skips_erc721_transfers() {
+        let client = client_for(&stub_contract_node(token_contract).await);
+
+        let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+        let (from, to) = (Address::from_low_u64_be(0x2222), Address::from_low_u64_be(0x3333));
+        let erc20_transfer = Log {
+            address: Address::from_low_u64_be(0x1111),
+            topics: vec![transfer_topic, H256::from(from), H256::from(to)],
+            data: ethers::abi::encode(&[Token::Uint(U256::from(1_500_000))]).into(),
+            log_index: Some(U256::from(3)),
+            ..Default::default()
+        };
+        // ERC-721 Transfer has the same signature with the token id as a fourth, indexed topic
+        let erc721_transfer = Log {
+            address: Address::from_low_u64_be(0x4444),
+            topics: vec![transfer_topic, H256::from(from), H256::from(to), H256::from_low_u64_be(42)],
+            data: Bytes::default(),
+            log_index: Some(U256::from(4)),
+            ..Default::default()
+        };
+
+        let erc20_logs = client.decode_erc20_logs(&[erc20_transfer, erc721_transfer]).await.unwrap();
+        assert_eq!(erc20_logs.len(), 1);
+        let erc20_log = serde_json::to_value(&erc20_logs[0]).unwrap();
+        assert_eq!(erc20_log["event"], "transfer");
+        assert_eq!(erc20_log["token"], to_checksum(&Address::from_low_u64_be(0x1111), None));
+        assert_eq!(erc20_log["symbol"], "TKN");
+        assert_eq!(erc20_log["log_index"], 3);
+        assert_eq!(erc20_log["from"], to_checksum(&from, None));
+        assert_eq!(erc20_log["amount"], "1.500000");
+        assert_eq!(erc20_log["amount_raw"], "1500000");
+    }
+
+    #[test]
+    fn test_has_valid_checksum() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = checksummed.parse::<Address>().unwrap();
+
+        assert!(has_valid_checksum(checksummed, &address));
+        assert!(has_valid_checksum(&checksummed.to_lowercase(), &address));
+        assert!(has_valid_checksum(&checksummed.to_uppercase().replacen("0X", "0x", 1), &address));
+        assert!(!has_valid_checksum("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD", &address));
+        assert_eq!(checksum_addresses(&[address]), vec![checksummed.to_string()]);
+    }
+
+    #[test]
+    fn test_proxy_detection_helpers() {
+        assert_eq!(
+            eip1967_slot("eip1967.proxy.implementation"),
+            "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc".parse::<H256>().unwrap(),
+        );
+
+        let implementation = Address::from_low_u64_be(0xbeef);
+        let code = [EIP1167_CODE_PREFIX.as_slice(), implementation.as_bytes(), EIP1167_CODE_SUFFIX.as_slice()].concat();
+        assert_eq!(eip1167_implementation(&Bytes::from(code)), Some(implementation));
+        assert_eq!(eip1167_implementation(&Bytes::from(vec![0x60, 0x80])), None);
+
+        assert_eq!(slot_address(H256::from(implementation)), Some(implementation));
+        assert_eq!(slot_address(H256::zero()), None);
+    }
+
+    #[test]
+    fn test_substitute_token_id() {
+        assert_eq!(
+            substitute_token_id("https://game.example/api/item/{id}.json", U256::from(314592)),
+            "https://game.example/api/item/000000000000000000000000000000000000000000000000000000000004cce0.json",
+        );
+        assert_eq!(substitute_token_id("ipfs://bafy/1.json", U256::from(1)), "ipfs://bafy/1.json");
+    }
+
+    #[test]
+    fn test_decode_non_standard_token_metadata() {
+        let abi_string = Bytes::from(ethers::abi::encode(&[Token::String("Dai Stablecoin".to_string())]));
+        assert_eq!(decode_metadata_string(&abi_string).as_deref(), Some("Dai Stablecoin"));
+
+        let mut bytes32 = [0u8; 32];
+        bytes32[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_metadata_string(&Bytes::from(bytes32.to_vec())).as_deref(), Some("MKR"));
+        assert_eq!(decode_metadata_string(&Bytes::from(vec![0u8; 32])), None);
+        assert_eq!(decode_metadata_string(&Bytes::default()), None);
+
+        // Resolver answers are untrusted, an EOA resolver answers empty data
+        assert_eq!(decode_string(&abi_string).as_deref(), Some("Dai Stablecoin"));
+        assert_eq!(decode_string(&Bytes::default()), None);
+        assert_eq!(decode_string(&Bytes::from(bytes32.to_vec())), None);
+        assert_eq!(decode_address(&Bytes::from(vec![0xff; 32])), None);
+
+        let mut decimals = [0u8; 32];
+        decimals[31] = 18;
+        assert_eq!(decode_decimals(&Bytes::from(decimals.to_vec())), Some(18));
+        decimals[30] = 1;
+        assert_eq!(decode_decimals(&Bytes::from(decimals.to_vec())), None);
+        assert_eq!(decode_decimals(&Bytes::default()), None);
+    }
+
+    #[test]
+    fn test_parse_token_amount() {
+        assert_eq!(parse_token_amount("1.5", 6).unwrap(), U256::from(1_500_000));
+        assert_eq!(parse_token_amount(".25", 2).unwrap(), U256::from(25));
+        assert_eq!(parse_token_amount("42", 0).unwrap(), U256::from(42));
+        assert_eq!(parse_token_amount("1.", 18).unwrap(), U256::exp10(18));
+        for invalid in ["", ".", "-1", "1e18", "1.2.3", "0.1234567"] {
+            assert_eq!(parse_token_amount(invalid, 6).unwrap_err().code(), "invalid_request", "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_decode_revert_data_and_priority_fees() {
+        let error_string = [&String::selector()[..], &ethers::abi::encode(&[Token::String("insufficient balance".to_string())])].concat();
+        assert_eq!(decode_revert_data(&error_string).as_deref(), Some("insufficient balance"));
+        let panic = [&PANIC_SELECTOR[..], &ethers::abi::encode(&[Token::Uint(U256::from(0x11))])].concat();
+        assert_eq!(decode_revert_data(&panic).as_deref(), Some("panic code 0x11"));
+        assert_eq!(decode_revert_data(&[0xe4, 0x50, 0xd3, 0x8c, 0x00]).as_deref(), Some("custom error 0xe450d38c"));
+        assert_eq!(decode_revert_data(&[]), None);
+
+        let gwei = |fees: [u64; 3]| fees.iter().map(|fee| U256::from(*fee) * U256::exp10(9)).collect::<Vec<_>>();
+        let rewards = vec![gwei([1, 2, 3]), gwei([0, 0, 0]), gwei([2, 4, 6]), gwei([3, 6, 9])];
+        assert_eq!(median_priority_fees(&rewards), gwei([2, 4, 6]));
+        assert_eq!(median_priority_fees(&[]), vec![U256::zero(); 3]);
+    }
+
+    #[test]
+    fn test_runtime_abi_call_encoding() {
+        let abi = parse_abi_value(json!([
+            { "type": "function", "name": "get", "stateMutability": "view", "inputs": [{ "name": "id", "type": "uint256" }], "outputs": [] },
+            { "type": "function", "name": "get", "stateMutability": "view", "inputs": [{ "name": "account", "type": "address" }], "outputs": [] },
+            { "type": "function", "name": "pair", "stateMutability": "view", "inputs": [], "outputs": [] },
+        ])).unwrap();
+        assert_eq!(input_signature(select_function(&abi, "get(address)", 0).unwrap()), "get(address)");
+        assert_eq!(input_signature(select_function(&abi, "pair", 3).unwrap()), "pair()");
+        assert_eq!(select_function(&abi, "get", 1).unwrap_err().code(), "invalid_request");
+        assert_eq!(select_function(&abi, "missing", 0).unwrap_err().code(), "invalid_request");
+
+        assert_eq!(json_to_token(&ParamType::Uint(256), &json!(42)), Ok(Token::Uint(U256::from(42))));
+        assert_eq!(json_to_token(&ParamType::Uint(8), &json!("0xff")), Ok(Token::Uint(U256::from(255))));
+        assert!(json_to_token(&ParamType::Uint(8), &json!("256")).is_err());
+        assert!(json_to_token(&ParamType::Uint(256), &json!(-1)).is_err());
+        assert_eq!(json_to_token(&ParamType::Int(8), &json!("-128")), Ok(Token::Int(I256::from(-128).into_raw())));
+        assert!(json_to_token(&ParamType::Int(8), &json!(128)).is_err());
+        assert!(json_to_token(&ParamType::FixedBytes(4), &json!("0x0102")).is_err());
+        let tuple = ParamType::Tuple(vec![ParamType::Address, ParamType::Array(Box::new(ParamType::Bool))]);
+        assert_eq!(
+            json_to_token(&tuple, &json!(["0x0000000000000000000000000000000000001111", [true, false]])),
+            Ok(Token::Tuple(vec![
+                Token::Address(Address::from_low_u64_be(0x1111)),
+                Token::Array(vec![Token::Bool(true), Token::Bool(false)]),
+            ])),
+        );
+
+        assert_eq!(token_to_json(Token::Int(I256::from(-5).into_raw())), json!("-5"));
+        assert_eq!(
+            token_to_json(Token::Tuple(vec![Token::Address(Address::from_low_u64_be(0x1111)), Token::FixedBytes(vec![0xab, 0xcd])])),
+            json!(["0x0000000000000000000000000000000000001111", "0xabcd"]),
+        );
+    }
+}
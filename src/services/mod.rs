@@ -1,3 +1,10 @@
 pub mod blockchain_service;
 pub mod validation_service;
-mod ethereum;
\ No newline at end of file
+pub mod user_service;
+mod abi_registry;
+mod ethereum;
+mod token_cache;
+mod transfer_stream;
+pub mod upstream;
+#[cfg(test)]
+pub mod test_support;
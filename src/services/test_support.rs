@@ -0,0 +1,120 @@
+// Stub JSON-RPC nodes and clients for the tests that talk to an upstream
+use crate::environment::config::EnvConfig;
+use crate::prelude::*;
+use super::abi_registry::AbiRegistry;
+use super::ethereum::EthereumClient;
+use super::token_cache::TokenMetadataCache;
+use ethers::abi::{ParamType, Token};
+use ethers::types::{Bytes, U256};
+use ethers::utils::hex;
+use serde_json::Value;
+
+// Result of a JSON-RPC request, or the message of the error a node answers with
+pub type StubAnswer = Result<Value, String>;
+
+// Serve a JSON-RPC node over HTTP that answers every request with `answer(method, params)`
+pub async fn stub_node<F>(answer: F) -> String
+where
+    F: Fn(&str, &Value) -> StubAnswer + Clone + Send + Sync + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let provider_url = format!("http://{}", listener.local_addr().unwrap());
+    let router = Router::new().route("/", post(move |Json(request): Json<Value>| {
+        let response = match answer(request["method"].as_str().unwrap_or_default(), &request["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(message) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32000, "message": message } }),
+        };
+        async move { Json(response) }
+    }));
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    provider_url
+}
+
+// Serve a node that only answers eth_call, with `contract(calldata)`. Multicall3 aggregate3 calls are
+// unpacked and each inner call answered the same way.
+pub async fn stub_contract_node<F>(contract: F) -> String
+where
+    F: Fn(&[u8]) -> Vec<u8> + Clone + Send + Sync + 'static,
+{
+    stub_node(move |method, params| {
+        assert_eq!(method, "eth_call");
+        let data = call_data(params);
+        let output = match aggregate3_calls(&data) {
+            Some(inner_calls) => aggregate3_results(inner_calls.iter().map(|inner_call| contract(inner_call)).collect()),
+            None => contract(&data),
+        };
+        Ok(json!(Bytes::from(output)))
+    })
+    .await
+}
+
+// Calldata of an eth_call request
+pub fn call_data(params: &Value) -> Bytes {
+    let call = &params[0];
+    call["input"].as_str().or(call["data"].as_str()).unwrap().parse().unwrap()
+}
+
+// Hex function selector of a calldata, without 0x
+pub fn selector(data: &[u8]) -> String {
+    hex::encode(&data[..4])
+}
+
+// Inner calldatas of a Multicall3 aggregate3 call, None for any other call
+pub fn aggregate3_calls(data: &[u8]) -> Option<Vec<Bytes>> {
+    if selector(data) != "82ad56cb" {
+        return None;
+    }
+    let aggregated = ParamType::Array(Box::new(ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes])));
+    let Token::Array(inner_calls) = ethers::abi::decode(&[aggregated], &data[4..]).unwrap().remove(0) else {
+        unreachable!()
+    };
+    Some(
+        inner_calls
+            .into_iter()
+            .map(|inner_call| match inner_call {
+                Token::Tuple(mut fields) => Bytes::from(fields.remove(2).into_bytes().unwrap()),
+                _ => unreachable!(),
+            })
+            .collect(),
+    )
+}
+
+// aggregate3 output where every inner call succeeded with the given output
+pub fn aggregate3_results(outputs: Vec<Vec<u8>>) -> Vec<u8> {
+    let results = outputs.into_iter().map(|output| Token::Tuple(vec![Token::Bool(true), Token::Bytes(output)])).collect();
+    ethers::abi::encode(&[Token::Array(results)])
+}
+
+// name() "Token", symbol() "TKN" and decimals() of an ERC20, None for other calls
+pub fn erc20_metadata(data: &[u8], decimals: u8) -> Option<Vec<u8>> {
+    match selector(data).as_str() {
+        "06fdde03" => Some(ethers::abi::encode(&[Token::String("Token".to_string())])),
+        "95d89b41" => Some(ethers::abi::encode(&[Token::String("TKN".to_string())])),
+        "313ce567" => Some(ethers::abi::encode(&[Token::Uint(U256::from(decimals))])),
+        _ => None,
+    }
+}
+
+pub fn uint(value: U256) -> Vec<u8> {
+    ethers::abi::encode(&[Token::Uint(value)])
+}
+
+pub fn env_config(env_toml: &str) -> EnvConfig {
+    Config::builder()
+        .add_source(File::from_str(env_toml, config::FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+// Client of a single node chain that does not retry, with extra `settings` lines of the environment file
+pub fn client_with(provider_url: &str, settings: &str) -> EthereumClient {
+    let env_config = env_config(&format!("provider_url = \"{}\"\nrpc_max_retries = 0\n{}", provider_url, settings));
+    let token_cache = Arc::new(TokenMetadataCache::new(Duration::from_secs(60), 10));
+    EthereumClient::new(&env_config.chains()[0], &env_config, token_cache, Arc::new(AbiRegistry::load(None).unwrap())).unwrap()
+}
+
+pub fn client_for(provider_url: &str) -> EthereumClient {
+    client_with(provider_url, "")
+}
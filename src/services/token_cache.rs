@@ -0,0 +1,192 @@
+use ethers::types::Address;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::environment::config::TokenOverride;
+use crate::prelude::*;
+
+// Token metadata that does not change over the life of a standard ERC20 contract.
+// Fields are absent when the contract does not implement the optional ERC20 getter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenMetadata {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+impl TokenMetadata {
+    // Decimals amounts are formatted with, tokens without decimals are shown in raw units
+    pub fn unit_decimals(&self) -> u8 {
+        self.decimals.unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.symbol.is_none() && self.decimals.is_none()
+    }
+
+    // Configured values win over what the contract reports
+    pub fn with_override(self, token_override: &TokenOverride) -> Self {
+        TokenMetadata {
+            name: token_override.name.clone().or(self.name),
+            symbol: token_override.symbol.clone().or(self.symbol),
+            decimals: token_override.decimals.or(self.decimals),
+        }
+    }
+}
+
+// Size and hit counters of the cache since startup
+#[derive(Debug, Serialize)]
+pub struct TokenCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct CacheEntry {
+    metadata: TokenMetadata,
+    inserted_at: Instant,
+}
+
+// In-process TTL cache of token metadata keyed by chain and token address, bounded to `max_entries`
+pub struct TokenMetadataCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<(String, Address), CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TokenMetadataCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        TokenMetadataCache {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, chain: &str, token_address: Address) -> Option<TokenMetadata> {
+        let metadata = {
+            let mut entries = self.entries.lock().unwrap();
+            let key = (chain.to_string(), token_address);
+            match entries.get(&key) {
+                Some(entry) if entry.inserted_at.elapsed() < self.ttl => Some(entry.metadata.clone()),
+                Some(_) => {
+                    entries.remove(&key);
+                    None
+                }
+                None => None,
+            }
+        };
+
+        match metadata {
+            Some(_) => {
+                let hits = self.hits.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!("Token metadata cache hit chain={} token={:?} hits={} misses={}", chain, token_address, hits, self.misses.load(Ordering::Relaxed));
+            }
+            None => {
+                let misses = self.misses.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!("Token metadata cache miss chain={} token={:?} hits={} misses={}", chain, token_address, self.hits.load(Ordering::Relaxed), misses);
+            }
+        }
+        metadata
+    }
+
+    pub fn insert(&self, chain: &str, token_address: Address, metadata: TokenMetadata) {
+        if self.max_entries == 0 || self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let key = (chain.to_string(), token_address);
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            // Make room by dropping expired entries first, then the oldest one
+            entries.retain(|_, entry| entry.inserted_at.elapsed() < self.ttl);
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(key, CacheEntry { metadata, inserted_at: Instant::now() });
+    }
+
+    pub fn stats(&self) -> TokenCacheStats {
+        TokenCacheStats {
+            entries: self.entries.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    // Evict one token of a chain, or every token of the chain when none is given. Returns the number of evicted entries.
+    pub fn evict(&self, chain: &str, token_address: Option<Address>) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(entry_chain, address), _| entry_chain != chain || token_address.is_some_and(|token_address| *address != token_address));
+        let evicted = before - entries.len();
+        info!("Token metadata cache evicted={} chain={} token={:?} remaining={}", evicted, chain, token_address, entries.len());
+        evicted
+    }
+
+    // Evict every token of every chain. Returns the number of evicted entries.
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let evicted = entries.len();
+        entries.clear();
+        info!("Token metadata cache evicted={} remaining=0", evicted);
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(symbol: &str) -> TokenMetadata {
+        TokenMetadata { name: Some(format!("{} Token", symbol)), symbol: Some(symbol.to_string()), decimals: Some(18) }
+    }
+
+    #[test]
+    fn test_token_metadata_cache_expiry_and_capacity() {
+        let (first, second, third) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2), Address::from_low_u64_be(3));
+        let cache = TokenMetadataCache::new(Duration::from_secs(60), 2);
+
+        cache.insert("ethereum", first, metadata("ONE"));
+        cache.insert("ethereum", second, metadata("TWO"));
+        assert_eq!(cache.get("ethereum", first), Some(metadata("ONE")));
+        assert_eq!(cache.get("polygon", first), None);
+
+        // A third token pushes out the oldest entry
+        cache.insert("ethereum", third, metadata("THREE"));
+        assert_eq!(cache.get("ethereum", first), None);
+        assert_eq!(cache.get("ethereum", third), Some(metadata("THREE")));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (2, 2, 2));
+
+        assert_eq!(cache.evict("ethereum", Some(second)), 1);
+        assert_eq!(cache.get("ethereum", second), None);
+        assert_eq!(cache.clear(), 1);
+
+        // Evicting a token on one chain keeps it on the others
+        let chains = TokenMetadataCache::new(Duration::from_secs(60), 10);
+        chains.insert("ethereum", first, metadata("ONE"));
+        chains.insert("polygon", first, metadata("ONE"));
+        chains.insert("polygon", second, metadata("TWO"));
+        assert_eq!(chains.evict("ethereum", Some(first)), 1);
+        assert_eq!(chains.get("ethereum", first), None);
+        assert_eq!(chains.get("polygon", first), Some(metadata("ONE")));
+        assert_eq!(chains.evict("polygon", None), 2);
+
+        let expired = TokenMetadataCache::new(Duration::ZERO, 2);
+        expired.insert("ethereum", first, metadata("ONE"));
+        assert_eq!(expired.get("ethereum", first), None);
+    }
+}
@@ -0,0 +1,230 @@
+use ethers::providers::{FilterKind, Middleware};
+use ethers::types::{Address, H256, U256};
+use std::collections::VecDeque;
+use crate::prelude::*;
+use crate::domain::transfer::{TokenTransfer, TransferCursor};
+use crate::error::BlockchainServiceError;
+use crate::services::ethereum::{fetch_transfer_logs, to_token_transfer, IERC20};
+use crate::services::upstream::EthProvider;
+use futures::stream::{self, BoxStream, StreamExt};
+
+// Live feed of Transfer events for one token.
+// Waits for newHeads push notifications on websocket and IPC providers, otherwise polls an eth_newBlockFilter
+// so it works over plain HTTP providers, and reads logs for every block it has not seen yet.
+pub struct TransferStream {
+    provider: Arc<EthProvider>,
+    contract: IERC20<EthProvider>,
+    addresses: Vec<Address>,
+    decimals: u8,
+    // First block whose logs have not been read yet
+    next_block: u64,
+    // Chain head seen by the last block number request
+    head_block: u64,
+    // Transfers at or before this position were already delivered to the client
+    resume_after: Option<TransferCursor>,
+    block_filter: Option<U256>,
+    // Push notification of new blocks, polling the block filter takes over once it ends
+    new_heads: Option<BoxStream<'static, ()>>,
+    pending: VecDeque<TokenTransfer>,
+    poll_interval: Duration,
+    log_block_range: u64,
+    // Read the chain head on the first poll without waiting for a new block
+    needs_catch_up: bool,
+}
+
+impl TransferStream {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn subscribe(
+        contract: IERC20<EthProvider>,
+        addresses: Vec<Address>,
+        decimals: u8,
+        next_block: u64,
+        resume_after: Option<TransferCursor>,
+        new_heads: Option<BoxStream<'static, ()>>,
+        poll_interval: Duration,
+        log_block_range: u64,
+    ) -> Result<Self, BlockchainServiceError> {
+        let provider = contract.client();
+        // Subscribe up front so blocks mined while catching up still trigger a poll
+        let block_filter = match new_heads {
+            Some(_) => None,
+            None => Some(provider.new_filter(FilterKind::NewBlocks).await?),
+        };
+
+        Ok(TransferStream {
+            provider,
+            contract,
+            addresses,
+            decimals,
+            next_block,
+            head_block: 0,
+            resume_after,
+            block_filter,
+            new_heads,
+            pending: VecDeque::new(),
+            poll_interval,
+            log_block_range,
+            needs_catch_up: true,
+        })
+    }
+
+    pub fn into_stream(self) -> BoxStream<'static, TokenTransfer> {
+        stream::unfold(self, |mut transfer_stream| async move {
+            loop {
+                if let Some(transfer) = transfer_stream.pending.pop_front() {
+                    return Some((transfer, transfer_stream));
+                }
+                if let Err(e) = transfer_stream.poll().await {
+                    // Keep the subscription alive across transient upstream failures
+                    error!("Error polling token transfers: {:?}", e);
+                    tokio::time::sleep(transfer_stream.poll_interval).await;
+                }
+            }
+        })
+        .boxed()
+    }
+
+    // Read the logs of one window of blocks. While behind the chain head every poll reads the next window
+    // without waiting, so a long catch-up delivers transfers window by window instead of all at the end.
+    async fn poll(&mut self) -> Result<(), BlockchainServiceError> {
+        if self.needs_catch_up || self.next_block > self.head_block {
+            if !self.needs_catch_up && !self.has_new_blocks().await? {
+                return Ok(());
+            }
+            self.head_block = self.provider.get_block_number().await?.as_u64();
+            self.needs_catch_up = false;
+            if self.next_block > self.head_block {
+                return Ok(());
+            }
+        }
+
+        let window_end = self.next_block.saturating_add(self.log_block_range - 1).min(self.head_block);
+        let logs = fetch_transfer_logs(&self.contract, &self.addresses, self.next_block, window_end).await?;
+        for (transfer, meta) in logs {
+            let transfer = to_token_transfer(transfer, meta, self.decimals)?;
+            if self.resume_after.is_some_and(|cursor| transfer.cursor() <= cursor) {
+                continue;
+            }
+            self.pending.push_back(transfer);
+        }
+        self.next_block = window_end + 1;
+        Ok(())
+    }
+
+    // Wait for the next block notification, or one poll interval, and report whether the node has seen new blocks since the last poll
+    async fn has_new_blocks(&mut self) -> Result<bool, BlockchainServiceError> {
+        if let Some(new_heads) = self.new_heads.as_mut() {
+            match new_heads.next().await {
+                Some(()) => return Ok(true),
+                None => {
+                    warn!("New heads subscription ended, polling a block filter instead");
+                    self.new_heads = None;
+                }
+            }
+        }
+
+        tokio::time::sleep(self.poll_interval).await;
+
+        let block_filter = match self.block_filter {
+            Some(block_filter) => block_filter,
+            None => {
+                let block_filter = self.provider.new_filter(FilterKind::NewBlocks).await?;
+                self.block_filter = Some(block_filter);
+                // Blocks mined while the filter was missing are still covered by `next_block`
+                return Ok(true);
+            }
+        };
+
+        match self.provider.get_filter_changes::<_, H256>(block_filter).await {
+            Ok(new_blocks) => Ok(!new_blocks.is_empty()),
+            Err(e) => {
+                // Nodes drop idle or unknown filters, so install a fresh one on the next poll
+                self.block_filter = None;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl Drop for TransferStream {
+    fn drop(&mut self) {
+        // Release the node-side filter once the client disconnects
+        if let Some(block_filter) = self.block_filter.take() {
+            let provider = self.provider.clone();
+            tokio::spawn(async move {
+                let _ = provider.uninstall_filter(block_filter).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::{self, env_config};
+    use crate::services::upstream::UpstreamPool;
+    use ethers::providers::Provider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const SENDER_TOPIC: &str = "0x000000000000000000000000d87d2676b8bbd7d4bf7884089356f7bb82158cfe";
+    const RECIPIENT_TOPIC: &str = "0x0000000000000000000000009f8f72aa9304c8b593d555f12ef6589cc3a579a2";
+
+    // Serve a node at head block 10 with transfers at (block, log index) (1, 0), (2, 0), (2, 1) and (7, 0),
+    // counting the eth_getLogs requests
+    async fn stub_node(get_logs_requests: Arc<AtomicUsize>) -> String {
+        test_support::stub_node(move |method, params| {
+            Ok(match method {
+                "eth_newBlockFilter" => json!("0x1"),
+                "eth_blockNumber" => json!("0xa"),
+                "eth_getFilterChanges" => json!([]),
+                "eth_getLogs" => {
+                    get_logs_requests.fetch_add(1, Ordering::SeqCst);
+                    let block = |key: &str| u64::from_str_radix(params[0][key].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+                    let (from_block, to_block) = (block("fromBlock"), block("toBlock"));
+                    let logs = [(1u64, 0u64), (2, 0), (2, 1), (7, 0)]
+                        .into_iter()
+                        .filter(|(block_number, _)| (from_block..=to_block).contains(block_number))
+                        .map(|(block_number, log_index)| json!({
+                            "address": "0xca11bde05977b3631167028862be2a173976ca11",
+                            "topics": [TRANSFER_TOPIC, SENDER_TOPIC, RECIPIENT_TOPIC],
+                            "data": format!("0x{:064x}", 1000 + log_index),
+                            "blockHash": format!("0x{:064x}", block_number),
+                            "blockNumber": format!("0x{:x}", block_number),
+                            "transactionHash": format!("0x{:064x}", block_number * 10 + log_index),
+                            "transactionIndex": "0x0",
+                            "logIndex": format!("0x{:x}", log_index),
+                            "removed": false,
+                        }))
+                        .collect::<Vec<_>>();
+                    json!(logs)
+                }
+                _ => json!(true),
+            })
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_yields_per_window_and_skips_delivered_transfers() {
+        let get_logs_requests = Arc::new(AtomicUsize::new(0));
+        let provider_url = stub_node(get_logs_requests.clone()).await;
+        let env_config = env_config(&format!("provider_url = \"{}\"\nrpc_max_retries = 0", provider_url));
+        let upstream_pool = UpstreamPool::new(&env_config.chains()[0], &env_config).unwrap();
+        let contract = IERC20::new("0xcA11bde05977b3631167028862bE2a173976CA11".parse::<Address>().unwrap(), Arc::new(Provider::new(upstream_pool)));
+
+        // Resume after (2, 0) with windows of 5 blocks: [0, 4], [5, 9], [10, 10]
+        let resume_after = Some(TransferCursor::new(2, 0));
+        let transfer_stream = TransferStream::subscribe(contract, Vec::new(), 0, 0, resume_after, None, Duration::from_millis(10), 5).await.unwrap();
+        let mut transfers = transfer_stream.into_stream();
+
+        let transfer = transfers.next().await.unwrap();
+        assert_eq!((transfer.block_number, transfer.log_index, transfer.amount_raw.as_str()), (2, 1, "1001"));
+        // Delivered as soon as its window was read, before the rest of the range
+        assert_eq!(get_logs_requests.load(Ordering::SeqCst), 1);
+
+        let transfer = transfers.next().await.unwrap();
+        assert_eq!((transfer.block_number, transfer.log_index), (7, 0));
+        assert_eq!(get_logs_requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,1013 @@
+use ethers::providers::{Http, Ipc, JsonRpcClient, JsonRpcError, Provider, ProviderError, PubsubClient, RpcError, Ws};
+use ethers::types::{Bytes, U256, U64};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Weak};
+use std::time::Instant;
+use crate::prelude::*;
+use crate::environment::config::{ChainConfig, EnvConfig, ProviderEndpoint, ProviderStrategy};
+use crate::error::AppServerError;
+use futures::future;
+use futures::stream::BoxStream;
+
+// Provider used by the blockchain clients, spreading requests over the chain's upstream nodes
+pub type EthProvider = Provider<UpstreamPool>;
+
+// Prefix of the error returned without calling any upstream because all their circuits are open
+pub const CIRCUIT_OPEN: &str = "circuit open";
+
+// Prefix of the error returned when the quorum strategy's upstreams disagree. Every upstream was already asked,
+// so retrying would only repeat the fan-out.
+pub const QUORUM_NOT_REACHED: &str = "quorum not reached";
+
+// Prefix of the error returned when fewer upstreams accept requests than the quorum strategy needs
+pub const QUORUM_UNAVAILABLE: &str = "quorum unavailable";
+
+// Prefix of the error returned when a websocket or IPC upstream does not answer within the RPC timeout
+pub const REQUEST_TIMED_OUT: &str = "request timed out";
+
+// JSON-RPC error code nodes and hosted providers use for rate limiting
+const RPC_LIMIT_EXCEEDED: i64 = -32005;
+
+// Filters live on the node that installed them, so the upstream answering one of these methods is remembered
+// by filter id and gets every later request on the filter
+const FILTER_CREATING_METHODS: &[&str] = &[
+    "eth_newFilter",
+    "eth_newBlockFilter",
+    "eth_newPendingTransactionFilter",
+];
+const FILTER_METHODS: &[&str] = &[
+    "eth_getFilterChanges",
+    "eth_getFilterLogs",
+    "eth_uninstallFilter",
+];
+
+// Index of the block parameter of methods reading state at a block
+fn block_param_index(method: &str) -> Option<usize> {
+    match method {
+        "eth_getBlockByNumber" | "eth_getBlockTransactionCountByNumber" | "eth_getUncleCountByBlockNumber" => Some(0),
+        "eth_getBalance" | "eth_getCode" | "eth_getTransactionCount" | "eth_call" | "eth_estimateGas" | "eth_feeHistory" => Some(1),
+        "eth_getStorageAt" | "eth_getProof" => Some(2),
+        _ => None,
+    }
+}
+
+// A missing block parameter defaults to the latest block
+fn is_head_tag(block: &Value) -> bool {
+    block.is_null() || block == "latest"
+}
+
+// Whether the answer to a request depends on the head block of the node answering it
+fn reads_head(method: &str, params: &Value) -> bool {
+    match method {
+        "eth_blockNumber" => true,
+        "eth_getLogs" => params[0].get("blockHash").is_none() && (is_head_tag(&params[0]["fromBlock"]) || is_head_tag(&params[0]["toBlock"])),
+        method => block_param_index(method).is_some_and(|index| is_head_tag(&params[index])),
+    }
+}
+
+// Params of a head reading request with the head replaced by `block_number`
+fn pin_to_block(method: &str, params: &Value, block_number: U64) -> Value {
+    let block = json!(block_number);
+    let mut pinned = params.clone();
+    match (method, block_param_index(method), &mut pinned) {
+        ("eth_getLogs", _, Value::Array(items)) => {
+            for key in ["fromBlock", "toBlock"] {
+                if is_head_tag(&items[0][key]) {
+                    items[0][key] = block.clone();
+                }
+            }
+        }
+        (_, Some(index), Value::Array(items)) if index < items.len() => items[index] = block,
+        (_, Some(index), Value::Array(items)) if index == items.len() => items.push(block),
+        _ => {}
+    }
+    pinned
+}
+
+// Whether a JSON-RPC error answer means the node is throttling us rather than rejecting the request
+pub fn is_rate_limited(rpc_error: &JsonRpcError) -> bool {
+    let message = rpc_error.message.to_lowercase();
+    rpc_error.code == RPC_LIMIT_EXCEEDED || rpc_error.code == 429 || message.contains("rate limit") || message.contains("too many requests")
+}
+
+// Sending these again after a timeout or dropped connection may repeat their effect, so they are never retried
+const NON_IDEMPOTENT_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+];
+
+// Whether a node rejected a broadcast because it already has the transaction in its pool or chain
+fn is_already_known(e: &ProviderError) -> bool {
+    match e.as_error_response() {
+        Some(rpc_error) => {
+            let message = rpc_error.message.to_lowercase();
+            ["already known", "known transaction", "alreadyknown", "already imported"].iter().any(|known| message.contains(known))
+        }
+        None => false,
+    }
+}
+
+// Whether a failed request may succeed when sent again. Reverts and other node answers are final,
+// and non-idempotent methods are never sent twice.
+fn is_retryable(method: &str, e: &ProviderError) -> bool {
+    if NON_IDEMPOTENT_METHODS.contains(&method) {
+        return false;
+    }
+    match e {
+        ProviderError::HTTPError(e) => match e.status() {
+            Some(status) => status.as_u16() == 429 || status.is_server_error(),
+            // Connection resets, refused connections and timeouts
+            None => true,
+        },
+        ProviderError::CustomError(message) => ![CIRCUIT_OPEN, QUORUM_NOT_REACHED, QUORUM_UNAVAILABLE].iter().any(|prefix| message.starts_with(prefix)),
+        e => match e.as_error_response() {
+            Some(rpc_error) => is_rate_limited(rpc_error),
+            // Dropped websocket or IPC connections, and non JSON bodies proxies answer outages with
+            None => matches!(e, ProviderError::JsonRpcClientError(_)),
+        },
+    }
+}
+
+// Transport failures and throttling count against an upstream, any other answer proves it works
+fn is_upstream_failure(e: &ProviderError) -> bool {
+    match e.as_error_response() {
+        Some(rpc_error) => is_rate_limited(rpc_error),
+        None => true,
+    }
+}
+
+// Circuit breaker state of one upstream
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    // Requests flow normally
+    Closed,
+    // The upstream kept failing, requests skip it until the open period ends or a probe succeeds
+    Open,
+    // The open period ended, a single trial request closes or reopens the circuit while others keep skipping it
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // Start of the half-open trial in flight
+    trial_started_at: Option<Instant>,
+}
+
+// Connection to one upstream. Websocket and IPC connections are opened on first use and again after they fail.
+#[derive(Debug)]
+enum Transport {
+    Http(Http),
+    Ws { url: String, connection: tokio::sync::Mutex<Option<Ws>> },
+    Ipc { path: PathBuf, connection: tokio::sync::Mutex<Option<Ipc>> },
+}
+
+#[derive(Debug)]
+struct Upstream {
+    // Scheme, host and port only, provider urls often embed API keys
+    name: String,
+    transport: Transport,
+    // Bounds websocket and IPC requests, HTTP requests are bounded by the HTTP client
+    timeout: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+impl Upstream {
+    fn new(endpoint: ProviderEndpoint, http_client: &reqwest::Client, timeout: Duration) -> Self {
+        let (name, transport) = match endpoint {
+            ProviderEndpoint::Http(url) => (redact(&url), Transport::Http(Http::new_with_client(url, http_client.clone()))),
+            ProviderEndpoint::Ws(url) => (redact(&url), Transport::Ws { url: url.to_string(), connection: tokio::sync::Mutex::new(None) }),
+            ProviderEndpoint::Ipc(path) => (format!("ipc://{}", path.display()), Transport::Ipc { path, connection: tokio::sync::Mutex::new(None) }),
+        };
+
+        Upstream {
+            name,
+            transport,
+            timeout,
+            circuit: Mutex::new(Circuit { state: CircuitState::Closed, consecutive_failures: 0, opened_at: None, trial_started_at: None }),
+        }
+    }
+
+    async fn send(&self, method: &str, params: &Value) -> Result<Value, ProviderError> {
+        match &self.transport {
+            Transport::Http(http) => send_on(http, method, params).await,
+            Transport::Ws { url, connection } => {
+                let result = self.with_timeout(async {
+                    let ws = connected(connection, || Ws::connect(url.as_str())).await?;
+                    send_on(&ws, method, params).await
+                }).await;
+                forget_failed_connection(connection, &result).await;
+                result
+            }
+            Transport::Ipc { path, connection } => {
+                let result = self.with_timeout(async {
+                    let ipc = connected(connection, || Ipc::connect(path)).await?;
+                    send_on(&ipc, method, params).await
+                }).await;
+                forget_failed_connection(connection, &result).await;
+                result
+            }
+        }
+    }
+
+    async fn with_timeout<F>(&self, request: F) -> Result<Value, ProviderError>
+    where
+        F: std::future::Future<Output = Result<Value, ProviderError>>,
+    {
+        match tokio::time::timeout(self.timeout, request).await {
+            Ok(result) => result,
+            Err(_) => Err(ProviderError::CustomError(format!("{} after {:?} on {}", REQUEST_TIMED_OUT, self.timeout, self.name))),
+        }
+    }
+
+    // Whether the transport carries push subscriptions, which HTTP does not
+    fn supports_subscriptions(&self) -> bool {
+        !matches!(self.transport, Transport::Http(_))
+    }
+
+    // Subscribe to new block headers, on upstreams that support push subscriptions
+    async fn subscribe_new_heads(&self) -> Result<BoxStream<'static, ()>, ProviderError> {
+        match &self.transport {
+            Transport::Http(_) => Err(ProviderError::UnsupportedRPC),
+            Transport::Ws { url, connection } => subscribe_new_heads_on(connected(connection, || Ws::connect(url.as_str())).await?).await,
+            Transport::Ipc { path, connection } => subscribe_new_heads_on(connected(connection, || Ipc::connect(path)).await?).await,
+        }
+    }
+
+    fn circuit_state(&self) -> CircuitState {
+        self.circuit.lock().unwrap().state
+    }
+
+    // Whether a request may be sent, moving an open circuit to half-open once `open_duration` has passed.
+    // A half-open circuit admits one trial at a time. A trial that never reported back, e.g. because the
+    // caller was dropped, stops blocking once the request timeout has passed.
+    fn allows_requests(&self, chain: &str, open_duration: Duration) -> bool {
+        let mut circuit = self.circuit.lock().unwrap();
+        match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if circuit.opened_at.is_some_and(|opened_at| opened_at.elapsed() >= open_duration) => {
+                circuit.state = CircuitState::HalfOpen;
+                circuit.trial_started_at = Some(Instant::now());
+                info!("Upstream circuit half-open chain={} upstream={} circuit=half_open", chain, self.name);
+                true
+            }
+            CircuitState::Open => false,
+            CircuitState::HalfOpen if circuit.trial_started_at.is_some_and(|started_at| started_at.elapsed() < self.timeout) => false,
+            CircuitState::HalfOpen => {
+                circuit.trial_started_at = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    fn record_success(&self, chain: &str) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures = 0;
+        circuit.trial_started_at = None;
+        if circuit.state != CircuitState::Closed {
+            circuit.state = CircuitState::Closed;
+            circuit.opened_at = None;
+            info!("Upstream recovered chain={} upstream={} circuit=closed", chain, self.name);
+        }
+    }
+
+    fn record_failure(&self, chain: &str, failure_threshold: u32, e: &ProviderError) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures += 1;
+        circuit.trial_started_at = None;
+        warn!("Upstream request failed chain={} upstream={} failures={} error={}", chain, self.name, circuit.consecutive_failures, e);
+
+        // A failed trial reopens right away, a closed circuit opens after `failure_threshold` failures in a row
+        let should_open = match circuit.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => circuit.consecutive_failures >= failure_threshold,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Some(Instant::now());
+            error!("Ejecting upstream chain={} upstream={} after {} consecutive failures circuit=open", chain, self.name, circuit.consecutive_failures);
+        }
+    }
+}
+
+// Health of one upstream as reported by the health endpoint
+#[derive(Debug, Serialize)]
+pub struct UpstreamStatus {
+    pub upstream: String,
+    pub healthy: bool,
+    pub circuit: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+// Jittered exponential backoff between attempts of a retryable request
+#[derive(Debug, Clone)]
+struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    // Random delay in the upper half of min(max_delay, base_delay * 2^attempt)
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let half = backoff.as_millis() as u64 / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half + 1);
+        Duration::from_millis(half + jitter)
+    }
+}
+
+// JSON-RPC transport over all provider urls of a chain.
+// Transport failures fail over to the next upstream and open its circuit after repeated failures,
+// while JSON-RPC errors are answers from a working node and are returned as they are.
+// Retryable failures are sent again with jittered exponential backoff.
+#[derive(Debug, Clone)]
+pub struct UpstreamPool {
+    chain: String,
+    upstreams: Arc<Vec<Upstream>>,
+    strategy: ProviderStrategy,
+    quorum: usize,
+    failure_threshold: u32,
+    circuit_open_duration: Duration,
+    retry: RetryPolicy,
+    next_upstream: Arc<AtomicUsize>,
+    // Index of the upstream that installed each filter
+    filter_upstreams: Arc<Mutex<HashMap<U256, usize>>>,
+}
+
+impl UpstreamPool {
+    pub fn new(chain: &ChainConfig, env_config: &EnvConfig) -> Result<Self, AppServerError> {
+        // Bound every request so a stalled node surfaces as an upstream timeout
+        let rpc_timeout = Duration::from_secs(env_config.rpc_timeout_secs.max(1));
+        let http_client = reqwest::Client::builder()
+            .timeout(rpc_timeout)
+            .build()
+            .map_err(|e| AppServerError::ConfigError(format!("Failed to build HTTP client: {}", e)))?;
+
+        let mut upstreams = Vec::with_capacity(chain.provider_urls.len());
+        for provider_url in &chain.provider_urls {
+            let endpoint = provider_url.parse::<ProviderEndpoint>().map_err(|e| {
+                AppServerError::ConfigError(format!("Chain {} has an invalid provider url: {}", chain.name, e))
+            })?;
+            upstreams.push(Upstream::new(endpoint, &http_client, rpc_timeout));
+        }
+        if upstreams.is_empty() {
+            return Err(AppServerError::ConfigError(format!("Chain {} has no provider url", chain.name)));
+        }
+
+        let upstream_pool = UpstreamPool {
+            chain: chain.name.clone(),
+            quorum: chain.quorum.unwrap_or(upstreams.len() / 2 + 1),
+            upstreams: Arc::new(upstreams),
+            strategy: chain.provider_strategy,
+            failure_threshold: env_config.provider_eject_after_failures.max(1),
+            circuit_open_duration: Duration::from_secs(env_config.circuit_open_secs),
+            retry: RetryPolicy {
+                max_retries: env_config.rpc_max_retries,
+                base_delay: Duration::from_millis(env_config.rpc_retry_base_delay_ms),
+                max_delay: Duration::from_millis(env_config.rpc_retry_max_delay_ms),
+            },
+            next_upstream: Arc::new(AtomicUsize::new(0)),
+            filter_upstreams: Arc::new(Mutex::new(HashMap::new())),
+        };
+        upstream_pool.spawn_probe(Duration::from_secs(env_config.provider_probe_interval_secs.max(1)));
+        Ok(upstream_pool)
+    }
+
+    pub fn status(&self) -> Vec<UpstreamStatus> {
+        self.upstreams
+            .iter()
+            .map(|upstream| {
+                let circuit = upstream.circuit.lock().unwrap();
+                UpstreamStatus {
+                    upstream: upstream.name.clone(),
+                    healthy: circuit.state != CircuitState::Open,
+                    circuit: circuit.state,
+                    consecutive_failures: circuit.consecutive_failures,
+                }
+            })
+            .collect()
+    }
+
+    // Periodically probe upstreams with an open circuit and close it once they answer again.
+    // The task ends when the pool is dropped.
+    fn spawn_probe(&self, probe_interval: Duration) {
+        let chain = self.chain.clone();
+        let upstreams: Weak<Vec<Upstream>> = Arc::downgrade(&self.upstreams);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(probe_interval).await;
+                let Some(upstreams) = upstreams.upgrade() else {
+                    break;
+                };
+
+                for upstream in upstreams.iter().filter(|upstream| upstream.circuit_state() == CircuitState::Open) {
+                    match upstream.send("eth_blockNumber", &Value::Null).await {
+                        Ok(_) => upstream.record_success(&chain),
+                        Err(e) => warn!("Upstream probe failed chain={} upstream={} circuit=open error={}", chain, upstream.name, e),
+                    }
+                }
+            }
+        });
+    }
+
+    // Upstreams accepting requests, in order starting at `start`.
+    // Evaluated lazily, so a half-open upstream is only claimed for a trial when the request actually reaches it.
+    fn candidates(&self, start: usize) -> impl Iterator<Item = &Upstream> {
+        self.indexed_candidates(start).map(|(_, upstream)| upstream)
+    }
+
+    fn indexed_candidates(&self, start: usize) -> impl Iterator<Item = (usize, &Upstream)> {
+        let count = self.upstreams.len();
+        (0..count)
+            .map(move |i| (start + i) % count)
+            .map(|index| (index, &self.upstreams[index]))
+            .filter(|(_, upstream)| upstream.allows_requests(&self.chain, self.circuit_open_duration))
+    }
+
+    // Upstream the strategy sends the next request to first
+    fn start(&self) -> usize {
+        match self.strategy {
+            ProviderStrategy::RoundRobin => self.next_upstream.fetch_add(1, Ordering::Relaxed),
+            ProviderStrategy::PrimaryFallback | ProviderStrategy::Quorum => 0,
+        }
+    }
+
+    // Push notification of new blocks from the first available websocket or IPC upstream.
+    // None when the chain has no such upstream, so callers fall back to polling.
+    // HTTP upstreams are left out before their circuit is asked, so they never claim a half-open trial.
+    pub async fn subscribe_new_heads(&self) -> Option<BoxStream<'static, ()>> {
+        let upstreams = self
+            .upstreams
+            .iter()
+            .filter(|upstream| upstream.supports_subscriptions())
+            .filter(|upstream| upstream.allows_requests(&self.chain, self.circuit_open_duration));
+        for upstream in upstreams {
+            match upstream.subscribe_new_heads().await {
+                Ok(new_heads) => {
+                    upstream.record_success(&self.chain);
+                    return Some(new_heads);
+                }
+                Err(e) => {
+                    if is_upstream_failure(&e) {
+                        upstream.record_failure(&self.chain, self.failure_threshold, &e);
+                    } else {
+                        upstream.record_success(&self.chain);
+                    }
+                    warn!("New heads subscription failed chain={} upstream={} error={}", self.chain, upstream.name, e);
+                }
+            }
+        }
+        None
+    }
+
+    fn circuit_open_error(&self) -> ProviderError {
+        ProviderError::CustomError(format!("{} for every upstream of chain {}", CIRCUIT_OPEN, self.chain))
+    }
+
+    async fn request_with_fallback(&self, method: &str, params: &Value, start: usize) -> Result<Value, ProviderError> {
+        self.request_answered_by(method, params, start).await.map(|(_, result)| result)
+    }
+
+    // Result of a request sent with fallback, along with the index of the upstream that answered it
+    async fn request_answered_by(&self, method: &str, params: &Value, start: usize) -> Result<(usize, Value), ProviderError> {
+        let mut last_error = None;
+        for (index, upstream) in self.indexed_candidates(start) {
+            match upstream.send(method, params).await {
+                Ok(result) => {
+                    upstream.record_success(&self.chain);
+                    return Ok((index, result));
+                }
+                Err(e) if !is_upstream_failure(&e) => {
+                    upstream.record_success(&self.chain);
+                    return Err(e);
+                }
+                Err(e) => {
+                    upstream.record_failure(&self.chain, self.failure_threshold, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| self.circuit_open_error()))
+    }
+
+    // Ask every available upstream and answer once `quorum` of them returned the same result or the same JSON-RPC error.
+    // Nodes a block apart answer reads at the head differently, so those are pinned to the agreed head first.
+    async fn request_quorum(&self, method: &str, params: &Value) -> Result<Value, ProviderError> {
+        let upstreams = self.candidates(0).collect::<Vec<_>>();
+        if upstreams.len() < self.quorum {
+            return Err(ProviderError::CustomError(format!(
+                "{}: {} of {} upstreams of chain {} are available, {} are needed",
+                QUORUM_UNAVAILABLE, upstreams.len(), self.upstreams.len(), self.chain, self.quorum
+            )));
+        }
+
+        let pinned;
+        let params = if reads_head(method, params) {
+            let head = self.agreed_head(&upstreams).await?;
+            if method == "eth_blockNumber" {
+                return Ok(json!(head));
+            }
+            pinned = pin_to_block(method, params, head);
+            &pinned
+        } else {
+            params
+        };
+
+        let responses = future::join_all(upstreams.iter().map(|upstream| upstream.send(method, params))).await;
+
+        let mut results: Vec<(Value, usize)> = Vec::new();
+        let mut rpc_errors: Vec<(ProviderError, usize)> = Vec::new();
+        let mut last_error = None;
+        let mut answered = 0;
+        for (upstream, response) in upstreams.iter().zip(responses) {
+            match response {
+                Ok(result) => {
+                    upstream.record_success(&self.chain);
+                    answered += 1;
+                    match results.iter_mut().find(|(agreed, _)| *agreed == result) {
+                        Some((_, votes)) => *votes += 1,
+                        None => results.push((result, 1)),
+                    }
+                }
+                Err(e) if !is_upstream_failure(&e) => {
+                    upstream.record_success(&self.chain);
+                    answered += 1;
+                    let found = rpc_errors.iter_mut().find(|(agreed, _)| same_rpc_error(agreed, &e));
+                    match found {
+                        Some((_, votes)) => *votes += 1,
+                        None => rpc_errors.push((e, 1)),
+                    }
+                }
+                Err(e) => {
+                    upstream.record_failure(&self.chain, self.failure_threshold, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some((result, _)) = results.into_iter().find(|(_, votes)| *votes >= self.quorum) {
+            return Ok(result);
+        }
+        if let Some((e, _)) = rpc_errors.into_iter().find(|(_, votes)| *votes >= self.quorum) {
+            return Err(e);
+        }
+        match last_error {
+            // No node answered at all, report the transport failure itself
+            Some(e) if answered == 0 => Err(e),
+            _ => Err(ProviderError::CustomError(format!(
+                "{}: {} upstreams must agree on {} on chain {}",
+                QUORUM_NOT_REACHED, self.quorum, method, self.chain
+            ))),
+        }
+    }
+
+    // Highest block at least `quorum` of the upstreams have reached
+    async fn agreed_head(&self, upstreams: &[&Upstream]) -> Result<U64, ProviderError> {
+        let responses = future::join_all(upstreams.iter().map(|upstream| upstream.send("eth_blockNumber", &Value::Null))).await;
+
+        let mut heads = Vec::with_capacity(upstreams.len());
+        let mut last_error = None;
+        for (upstream, response) in upstreams.iter().zip(responses) {
+            match response.and_then(|head| Ok(serde_json::from_value::<U64>(head)?)) {
+                Ok(head) => {
+                    upstream.record_success(&self.chain);
+                    heads.push(head);
+                }
+                Err(e) => {
+                    if is_upstream_failure(&e) {
+                        upstream.record_failure(&self.chain, self.failure_threshold, &e);
+                    } else {
+                        upstream.record_success(&self.chain);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        heads.sort_unstable_by(|a, b| b.cmp(a));
+        match (heads.get(self.quorum.max(1) - 1), last_error) {
+            (Some(head), _) => Ok(*head),
+            // No node answered at all, report the failure itself
+            (None, Some(e)) if heads.is_empty() => Err(e),
+            (None, _) => Err(ProviderError::CustomError(format!(
+                "{}: {} upstreams must agree on the head block on chain {}",
+                QUORUM_NOT_REACHED, self.quorum, self.chain
+            ))),
+        }
+    }
+
+    // Send a request on a filter to the upstream that installed it. Filters of unknown ids, e.g. installed before
+    // a restart, go through fallback and get the node's own answer.
+    async fn request_filter(&self, method: &str, params: &Value) -> Result<Value, ProviderError> {
+        let filter_id = serde_json::from_value::<U256>(params[0].clone()).ok();
+        let index = filter_id.and_then(|filter_id| self.filter_upstreams.lock().unwrap().get(&filter_id).copied());
+        let Some(index) = index else {
+            return self.request_with_fallback(method, params, 0).await;
+        };
+
+        let upstream = &self.upstreams[index];
+        let result = upstream.send(method, params).await;
+        match &result {
+            Ok(_) => upstream.record_success(&self.chain),
+            Err(e) if is_upstream_failure(e) => upstream.record_failure(&self.chain, self.failure_threshold, e),
+            Err(_) => upstream.record_success(&self.chain),
+        }
+        // A node forgets a filter once uninstalled or expired
+        if method == "eth_uninstallFilter" || result.as_ref().is_err_and(|e| e.as_error_response().is_some()) {
+            if let Some(filter_id) = filter_id {
+                self.filter_upstreams.lock().unwrap().remove(&filter_id);
+            }
+        }
+        result
+    }
+
+    // Broadcast a signed transaction through a single upstream, without retries or quorum.
+    // Sending it again or to several nodes gets "already known" or "nonce too low" answers for a transaction that went out,
+    // so an "already known" answer counts as success.
+    pub async fn send_raw_transaction(&self, raw_transaction: &Bytes) -> Result<(), ProviderError> {
+        let Some(upstream) = self.candidates(self.start()).next() else {
+            return Err(self.circuit_open_error());
+        };
+
+        match upstream.send("eth_sendRawTransaction", &json!([raw_transaction])).await {
+            Ok(_) => {
+                upstream.record_success(&self.chain);
+                Ok(())
+            }
+            Err(e) if is_already_known(&e) => {
+                upstream.record_success(&self.chain);
+                info!("Transaction already known chain={} upstream={}", self.chain, upstream.name);
+                Ok(())
+            }
+            Err(e) => {
+                if is_upstream_failure(&e) {
+                    upstream.record_failure(&self.chain, self.failure_threshold, &e);
+                } else {
+                    upstream.record_success(&self.chain);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn dispatch(&self, method: &str, params: &Value) -> Result<Value, ProviderError> {
+        if FILTER_CREATING_METHODS.contains(&method) {
+            let (index, filter_id) = self.request_answered_by(method, params, self.start()).await?;
+            if let Ok(id) = serde_json::from_value::<U256>(filter_id.clone()) {
+                self.filter_upstreams.lock().unwrap().insert(id, index);
+            }
+            return Ok(filter_id);
+        }
+        if FILTER_METHODS.contains(&method) {
+            return self.request_filter(method, params).await;
+        }
+
+        match self.strategy {
+            ProviderStrategy::Quorum => self.request_quorum(method, params).await,
+            ProviderStrategy::PrimaryFallback | ProviderStrategy::RoundRobin => self.request_with_fallback(method, params, self.start()).await,
+        }
+    }
+}
+
+// Scheme, host and port of a provider url, leaving out paths and credentials that may hold API keys
+fn redact(url: &reqwest::Url) -> String {
+    format!("{}://{}:{}", url.scheme(), url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or_default())
+}
+
+async fn send_on<C: JsonRpcClient>(client: &C, method: &str, params: &Value) -> Result<Value, ProviderError> {
+    // Keep parameterless calls free of a `params` member, like the transports send them on their own
+    let result = if params.is_null() {
+        client.request(method, ()).await
+    } else {
+        client.request(method, params).await
+    };
+    result.map_err(Into::into)
+}
+
+// The open connection, or a new one when there is none yet
+async fn connected<C, F, Fut, E>(connection: &tokio::sync::Mutex<Option<C>>, connect: F) -> Result<C, ProviderError>
+where
+    C: Clone,
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<C, E>>,
+    E: Into<ProviderError>,
+{
+    let mut connection = connection.lock().await;
+    if let Some(client) = connection.as_ref() {
+        return Ok(client.clone());
+    }
+
+    let client = connect().await.map_err(Into::into)?;
+    *connection = Some(client.clone());
+    Ok(client)
+}
+
+// Drop a connection that failed below JSON-RPC so the next request reconnects
+async fn forget_failed_connection<C>(connection: &tokio::sync::Mutex<Option<C>>, result: &Result<Value, ProviderError>) {
+    if let Err(e) = result {
+        if e.as_error_response().is_none() {
+            *connection.lock().await = None;
+        }
+    }
+}
+
+// Turn a newHeads subscription into a stream that yields once per new block and unsubscribes when dropped
+async fn subscribe_new_heads_on<C>(client: C) -> Result<BoxStream<'static, ()>, ProviderError>
+where
+    C: PubsubClient<NotificationStream = futures::channel::mpsc::UnboundedReceiver<Box<serde_json::value::RawValue>>> + Clone + 'static,
+    C::Error: Into<ProviderError>,
+{
+    let id: U256 = client.request("eth_subscribe", ["newHeads"]).await.map_err(Into::into)?;
+    let notifications = client.subscribe(id).map_err(Into::into)?;
+    let subscription = Subscription { client, id };
+
+    Ok(notifications
+        .map(move |_| {
+            // Keep the subscription alive for as long as the stream
+            let _ = &subscription;
+        })
+        .boxed())
+}
+
+struct Subscription<C: PubsubClient + Clone + 'static> {
+    client: C,
+    id: U256,
+}
+
+impl<C: PubsubClient + Clone + 'static> Drop for Subscription<C> {
+    fn drop(&mut self) {
+        let _ = self.client.unsubscribe(self.id);
+        let (client, id) = (self.client.clone(), self.id);
+        tokio::spawn(async move {
+            let _ = client.request::<_, bool>("eth_unsubscribe", [id]).await;
+        });
+    }
+}
+
+fn same_rpc_error(a: &ProviderError, b: &ProviderError) -> bool {
+    match (a.as_error_response(), b.as_error_response()) {
+        (Some(a), Some(b)) => a.code == b.code && a.message == b.message && a.data == b.data,
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for UpstreamPool {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let mut attempt = 0;
+        let result = loop {
+            match self.dispatch(method, &params).await {
+                Err(e) if attempt < self.retry.max_retries && is_retryable(method, &e) => {
+                    let delay = self.retry.delay(attempt);
+                    attempt += 1;
+                    warn!("Retrying {} on chain {} in {:?} attempt={} error={}", method, self.chain, delay, attempt, e);
+                    tokio::time::sleep(delay).await;
+                }
+                result => break result?,
+            }
+        };
+
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support::{env_config, stub_node};
+    use ethers::providers::HttpClientError;
+
+    // Node that answers every request with `result`
+    async fn result_node(result: &'static str) -> String {
+        stub_node(move |_, _| Ok(json!(result))).await
+    }
+
+    // Node that answers every request with a JSON-RPC error and counts the requests
+    async fn error_node(message: &'static str, requests: Arc<AtomicUsize>) -> String {
+        stub_node(move |_, _| {
+            requests.fetch_add(1, Ordering::SeqCst);
+            Err(message.to_string())
+        })
+        .await
+    }
+
+    fn pool(provider_urls: Vec<String>, provider_strategy: ProviderStrategy, quorum: Option<usize>) -> UpstreamPool {
+        let env_config = env_config("provider_eject_after_failures = 1\nrpc_max_retries = 0");
+        let chain = ChainConfig {
+            name: "test".to_string(),
+            chain_id: None,
+            provider_urls,
+            provider_strategy,
+            quorum,
+            multicall_address: None,
+            ens_registry_address: None,
+            token_overrides: Vec::new(),
+        };
+        UpstreamPool::new(&chain, &env_config).unwrap()
+    }
+
+    fn unreachable_url() -> String {
+        "http://127.0.0.1:1".to_string()
+    }
+
+    #[tokio::test]
+    async fn test_upstream_pool_failover_and_quorum() {
+        // Nothing listens on port 1, so the primary fails and gets ejected
+        let unreachable = unreachable_url();
+        let fallback = pool(vec![unreachable.clone(), result_node("0x1").await], ProviderStrategy::PrimaryFallback, None);
+        let block_number: U64 = fallback.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block_number, U64::from(1));
+        let status = fallback.status();
+        assert_eq!(status[0].circuit, CircuitState::Open);
+        assert_eq!(status[1].circuit, CircuitState::Closed);
+
+        // Once every circuit is open requests fail fast without reaching any upstream
+        let unavailable = pool(vec![unreachable], ProviderStrategy::PrimaryFallback, None);
+        let result = unavailable.request::<_, U64>("eth_blockNumber", ()).await;
+        assert!(matches!(result, Err(ProviderError::HTTPError(_))));
+        let result = unavailable.request::<_, U64>("eth_blockNumber", ()).await;
+        assert!(matches!(result, Err(ProviderError::CustomError(message)) if message.starts_with(CIRCUIT_OPEN)));
+
+        let urls = vec![result_node("0x1").await, result_node("0x1").await, result_node("0x2").await];
+        let quorum = pool(urls.clone(), ProviderStrategy::Quorum, Some(2));
+        let block_number: U64 = quorum.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block_number, U64::from(1));
+
+        let unanimous = pool(urls, ProviderStrategy::Quorum, Some(3));
+        let result = unanimous.request::<_, U64>("eth_chainId", ()).await;
+        assert!(matches!(result, Err(ProviderError::CustomError(_))));
+
+        // With one of three circuits open a quorum of three cannot be asked, without claiming every circuit is open
+        let partly_open = pool(vec![unreachable_url(), result_node("0x1").await, result_node("0x1").await], ProviderStrategy::Quorum, Some(3));
+        assert!(partly_open.request::<_, U64>("eth_chainId", ()).await.is_err());
+        assert_eq!(partly_open.status()[0].circuit, CircuitState::Open);
+        let result = partly_open.request::<_, U64>("eth_chainId", ()).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::CustomError(message)) if message == format!("{}: 2 of 3 upstreams of chain test are available, 3 are needed", QUORUM_UNAVAILABLE)
+        ));
+    }
+
+    // Node at head block `head` where every account holds as much wei as the number of the block read at
+    async fn chain_node(head: u64) -> String {
+        stub_node(move |method, params| match method {
+            "eth_blockNumber" => Ok(json!(U64::from(head))),
+            "eth_getBalance" if params[1] == "latest" => Ok(json!(U256::from(head))),
+            "eth_getBalance" => Ok(json!(U256::from(serde_json::from_value::<U64>(params[1].clone()).unwrap().as_u64()))),
+            method => panic!("unexpected {}", method),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_quorum_pins_head_reads_to_the_agreed_block() {
+        let quorum = pool(vec![chain_node(16).await, chain_node(17).await], ProviderStrategy::Quorum, Some(2));
+        let account = "0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2";
+
+        // Both nodes have block 16, the latest one only has it
+        let block_number: U64 = quorum.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block_number, U64::from(16));
+        let balance: U256 = quorum.request("eth_getBalance", json!([account, "latest"])).await.unwrap();
+        assert_eq!(balance, U256::from(16));
+
+        // Explicit blocks are sent as they are
+        let balance: U256 = quorum.request("eth_getBalance", json!([account, "0xa"])).await.unwrap();
+        assert_eq!(balance, U256::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_filter_requests_follow_the_installing_upstream() {
+        // Node `node` installs filter ids node * 16 + n and only knows its own
+        let filter_node = |node: u64| {
+            let installed = Arc::new(AtomicUsize::new(0));
+            stub_node(move |method, params| match method {
+                "eth_newBlockFilter" => Ok(json!(U256::from(node * 16 + installed.fetch_add(1, Ordering::SeqCst) as u64))),
+                "eth_getFilterChanges" | "eth_uninstallFilter" => {
+                    let filter_id = serde_json::from_value::<U256>(params[0].clone()).unwrap();
+                    if filter_id.as_u64() / 16 == node {
+                        Ok(json!([format!("{:#x}", node)]))
+                    } else {
+                        Err("filter not found".to_string())
+                    }
+                }
+                method => panic!("unexpected {}", method),
+            })
+        };
+        let round_robin = pool(vec![filter_node(1).await, filter_node(2).await], ProviderStrategy::RoundRobin, None);
+
+        // Filters get installed round robin, and every later request on one reaches its node
+        let first: U256 = round_robin.request("eth_newBlockFilter", ()).await.unwrap();
+        let second: U256 = round_robin.request("eth_newBlockFilter", ()).await.unwrap();
+        assert_eq!((first.as_u64() / 16, second.as_u64() / 16), (1, 2));
+        for _ in 0..3 {
+            let changes: Vec<String> = round_robin.request("eth_getFilterChanges", [second]).await.unwrap();
+            assert_eq!(changes, vec!["0x2"]);
+            let changes: Vec<String> = round_robin.request("eth_getFilterChanges", [first]).await.unwrap();
+            assert_eq!(changes, vec!["0x1"]);
+        }
+
+        round_robin.request::<_, Value>("eth_uninstallFilter", [second]).await.unwrap();
+        assert!(!round_robin.filter_upstreams.lock().unwrap().contains_key(&second));
+    }
+
+    #[tokio::test]
+    async fn test_new_heads_subscription_claims_only_push_upstreams() {
+        // Nothing listens on port 1, with both circuits open and due for a trial
+        let mut pool = pool(vec!["http://127.0.0.1:1".to_string(), "ws://127.0.0.1:1".to_string()], ProviderStrategy::PrimaryFallback, None);
+        pool.circuit_open_duration = Duration::ZERO;
+        let failure = ProviderError::CustomError("connection refused".to_string());
+        for upstream in pool.upstreams.iter() {
+            upstream.record_failure("test", 1, &failure);
+        }
+
+        assert!(pool.subscribe_new_heads().await.is_none());
+        // The HTTP upstream keeps its trial for a request, the failed websocket trial reopened its circuit
+        let status = pool.status();
+        assert_eq!((status[0].circuit, status[0].consecutive_failures), (CircuitState::Open, 1));
+        assert_eq!((status[1].circuit, status[1].consecutive_failures), (CircuitState::Open, 2));
+        assert!(pool.upstreams[0].allows_requests("test", Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_send_raw_transaction_single_upstream() {
+        let raw_transaction = Bytes::from(vec![0x02, 0xc0]);
+
+        // Under quorum the broadcast still reaches a single node, and "already known" is a success
+        let requests = Arc::new(AtomicUsize::new(0));
+        let urls = vec![error_node("already known", requests.clone()).await, error_node("already known", requests.clone()).await];
+        let quorum = pool(urls, ProviderStrategy::Quorum, Some(2));
+        assert!(quorum.send_raw_transaction(&raw_transaction).await.is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let rejecting = pool(vec![error_node("nonce too low", requests.clone()).await], ProviderStrategy::PrimaryFallback, None);
+        let result = rejecting.send_raw_transaction(&raw_transaction).await;
+        assert!(result.unwrap_err().as_error_response().is_some_and(|rpc_error| rpc_error.message == "nonce too low"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_policy_and_retryable_errors() {
+        let retry = RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(100), max_delay: Duration::from_millis(2000) };
+        for attempt in 0..8 {
+            let backoff = (100u64 << attempt).min(2000);
+            let delay = retry.delay(attempt).as_millis() as u64;
+            assert!(delay >= backoff / 2 && delay <= backoff, "attempt {} delay {}", attempt, delay);
+        }
+
+        let rpc_error = |code: i64, message: &str| {
+            ProviderError::JsonRpcClientError(Box::new(HttpClientError::JsonRpcError(JsonRpcError { code, message: message.to_string(), data: None })))
+        };
+        assert!(is_retryable("eth_call", &rpc_error(-32005, "limit exceeded")));
+        assert!(!is_retryable("eth_call", &rpc_error(3, "execution reverted")));
+        assert!(!is_retryable("eth_call", &ProviderError::CustomError(format!("{} for every upstream of chain test", CIRCUIT_OPEN))));
+        assert!(!is_retryable("eth_call", &ProviderError::CustomError(format!("{}: 2 upstreams must agree on eth_call on chain test", QUORUM_NOT_REACHED))));
+        assert!(!is_retryable("eth_call", &ProviderError::CustomError(format!("{}: 2 of 3 upstreams of chain test are available, 3 are needed", QUORUM_UNAVAILABLE))));
+        let timed_out = ProviderError::CustomError(format!("{} after 10s on ws://node:8546", REQUEST_TIMED_OUT));
+        assert!(is_retryable("eth_call", &timed_out));
+        assert!(!is_retryable("eth_sendRawTransaction", &timed_out));
+        assert!(!is_retryable("eth_sendRawTransaction", &rpc_error(-32005, "limit exceeded")));
+    }
+
+    #[test]
+    fn test_half_open_circuit_admits_a_single_trial() {
+        let endpoint = "http://127.0.0.1:1".parse::<ProviderEndpoint>().unwrap();
+        let upstream = Upstream::new(endpoint, &reqwest::Client::new(), Duration::from_secs(10));
+        let failure = ProviderError::CustomError("connection refused".to_string());
+        upstream.record_failure("test", 1, &failure);
+        assert!(!upstream.allows_requests("test", Duration::from_secs(60)));
+
+        // Once the open period is over, the first caller gets the trial and the others keep failing fast
+        assert!(upstream.allows_requests("test", Duration::ZERO));
+        assert_eq!(upstream.circuit_state(), CircuitState::HalfOpen);
+        assert!(!upstream.allows_requests("test", Duration::ZERO));
+        assert!(!upstream.allows_requests("test", Duration::ZERO));
+
+        // A failed trial reopens the circuit, a successful one closes it for everyone
+        upstream.record_failure("test", 1, &failure);
+        assert_eq!(upstream.circuit_state(), CircuitState::Open);
+        assert!(upstream.allows_requests("test", Duration::ZERO));
+        upstream.record_success("test");
+        assert!(upstream.allows_requests("test", Duration::ZERO));
+        assert!(upstream.allows_requests("test", Duration::ZERO));
+    }
+}
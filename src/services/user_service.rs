@@ -0,0 +1,117 @@
+use ethers::types::{BlockId, U256};
+use ethers::utils::format_units;
+use crate::environment::config::AppConfig;
+use crate::error::{AppServerError, UserServiceError};
+use crate::prelude::*;
+use crate::domain::user::UserPortfolio;
+use crate::services::blockchain_service::BlockchainAdapter;
+
+// Users keyed by name, each owning one or more wallet addresses
+type UserAddresses = HashMap<String, Vec<String>>;
+
+pub struct UserService {
+    users: UserAddresses,
+}
+
+impl UserService {
+    pub fn new(app_config: &AppConfig) -> Result<Self, AppServerError> {
+        let users = get_user_addresses_data_from_json(&app_config.environment.users_file)?;
+        info!("Loaded {} users from {}", users.len(), app_config.environment.users_file);
+
+        Ok(Self { users })
+    }
+
+    // User names in a stable, sorted order
+    pub fn users(&self) -> Vec<&String> {
+        let mut users: Vec<&String> = self.users.keys().collect();
+        users.sort();
+        users
+    }
+
+    // Balances of every address owned by the user plus their exact sum
+    pub async fn get_user_portfolio(&self, blockchain_client: &dyn BlockchainAdapter, user: &str, token_address: &str, block: Option<BlockId>) -> Result<UserPortfolio, UserServiceError> {
+        let addresses = self.users
+            .get(user)
+            .ok_or_else(|| UserServiceError::UserNotFound(user.to_string()))?;
+
+        let balances = blockchain_client.get_account_balance(token_address, addresses.clone(), false, block).await?;
+        // Read after the balances, which leave the metadata in the cache
+        let metadata = blockchain_client.get_token_metadata(token_address, block).await?;
+
+        // Sum the raw balances so the total is exact, rather than adding up formatted decimals
+        let mut total = U256::zero();
+        for account_balance in &balances {
+            let balance = U256::from_dec_str(&account_balance.balance_raw).map_err(|e| {
+                UserServiceError::InvalidBalance(format!("address [{}] {:?}", account_balance.address, e))
+            })?;
+            total = total.checked_add(balance).ok_or_else(|| {
+                UserServiceError::InvalidBalance(format!("total balance of user [{}] overflows", user))
+            })?;
+        }
+        let total_str = format_units(total, u32::from(metadata.unit_decimals()))?;
+
+        Ok(UserPortfolio::new(user.to_string(), metadata.symbol, balances, total_str, total.to_string()))
+    }
+}
+
+fn get_user_addresses_data_from_json(filename: &str) -> Result<UserAddresses, AppServerError> {
+    let data = fs::read_to_string(filename)?;
+    let users_addresses: UserAddresses = from_str(&data).map_err(|e| {
+        AppServerError::ConfigError(format!("Invalid users file {}: {}", filename, e))
+    })?;
+    Ok(users_addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ethereum::EthereumClient;
+    use crate::services::test_support::{client_for, erc20_metadata, selector, stub_contract_node, uint};
+
+    // Client of a node where every address is an 18 decimals token, in which 0x…1111 holds `first`
+    // and every other address holds `second`
+    async fn token_client(first: U256, second: U256) -> EthereumClient {
+        let provider_url = stub_contract_node(move |data| {
+            if let Some(output) = erc20_metadata(data, 18) {
+                return output;
+            }
+            match selector(data).as_str() {
+                "70a08231" if data.ends_with(&[0x11, 0x11]) => uint(first),
+                "70a08231" => uint(second),
+                selector => panic!("unexpected call {}", selector),
+            }
+        })
+        .await;
+        client_for(&provider_url)
+    }
+
+    fn user_service() -> UserService {
+        let addresses = vec![
+            "0x0000000000000000000000000000000000001111".to_string(),
+            "0x0000000000000000000000000000000000002222".to_string(),
+        ];
+        UserService { users: HashMap::from([("alice".to_string(), addresses)]) }
+    }
+
+    #[tokio::test]
+    async fn test_get_user_portfolio_sums_exactly() {
+        // 0.1 + 0.2 is not 0.3 in floating point, the raw sum is
+        let client = token_client(U256::exp10(17), U256::exp10(17) * 2).await;
+        let token = "0x000000000000000000000000000000000000aaaa";
+
+        let portfolio = user_service().get_user_portfolio(&client, "alice", token, None).await.unwrap();
+        assert_eq!(portfolio.symbol.as_deref(), Some("TKN"));
+        assert_eq!(portfolio.balances.len(), 2);
+        assert_eq!(portfolio.total, "0.300000000000000000");
+        assert_eq!(portfolio.total_raw, "300000000000000000");
+
+        assert!(matches!(user_service().get_user_portfolio(&client, "bob", token, None).await, Err(UserServiceError::UserNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_portfolio_total_overflow() {
+        let client = token_client(U256::MAX, U256::one()).await;
+        let result = user_service().get_user_portfolio(&client, "alice", "0x000000000000000000000000000000000000aaaa", None).await;
+        assert!(matches!(result, Err(UserServiceError::InvalidBalance(message)) if message.contains("overflows")));
+    }
+}